@@ -0,0 +1,118 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::PathBuf;
+
+/// What a device can be used for, derived from its capabilities.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceRole {
+    Keyboard,
+    Keypad,
+    Mouse,
+    Gamepad,
+    /// Any other device with buttons, such as macro pads and foot pedals.
+    Buttons,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub phys: Option<String>,
+    pub roles: Vec<DeviceRole>,
+}
+
+impl DeviceInfo {
+    pub fn new(
+        path: PathBuf,
+        device: &evdev::raw_stream::RawDevice,
+        roles: Vec<DeviceRole>,
+    ) -> Self {
+        let id = device.input_id();
+
+        Self {
+            path,
+            name: device.name().unwrap_or_default().to_string(),
+            vendor: id.vendor(),
+            product: id.product(),
+            phys: device.physical_path().map(str::to_string),
+            roles,
+        }
+    }
+
+    /// Vendor and product IDs formatted as `vvvv:pppp`.
+    pub fn id(&self) -> String {
+        format!("{:04x}:{:04x}", self.vendor, self.product)
+    }
+}
+
+/// Identity and device nodes of the virtual device the daemon clicks through.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VirtualDeviceInfo {
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+    pub bustype: String,
+    pub dev_nodes: Vec<PathBuf>,
+}
+
+/// A registered device together with what the daemon does with it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceEntry {
+    pub info: DeviceInfo,
+    pub grabbed: bool,
+    /// Whether the current profile reacts to the device's events.
+    pub used: bool,
+}
+
+/// Matches a device by name, `vendor:product` ID, phys path or devnode glob, every field that is set must match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeviceMatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phys: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_glob"
+    )]
+    pub devnode: Option<String>,
+}
+
+/// Rejects invalid patterns up front, an invalid pattern would otherwise never match.
+fn deserialize_glob<'de, D>(d: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = Option::<String>::deserialize(d)?;
+    if let Some(pattern) = pattern.as_ref() {
+        glob::Pattern::new(pattern).map_err(|e| {
+            serde::de::Error::custom(format!("invalid glob pattern `{pattern}`: {e}"))
+        })?;
+    }
+
+    Ok(pattern)
+}
+
+impl DeviceMatch {
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        let name_matches = self.name.as_ref().is_none_or(|name| *name == device.name);
+        let id_matches = self
+            .id
+            .as_ref()
+            .is_none_or(|id| id.eq_ignore_ascii_case(&device.id()));
+        let phys_matches = self
+            .phys
+            .as_ref()
+            .is_none_or(|phys| device.phys.as_ref() == Some(phys));
+        let devnode_matches = self.devnode.as_ref().is_none_or(|devnode| {
+            glob::Pattern::new(devnode).is_ok_and(|pattern| pattern.matches_path(&device.path))
+        });
+
+        name_matches && id_matches && phys_matches && devnode_matches
+    }
+}
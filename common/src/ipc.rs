@@ -1,38 +1,116 @@
-use crate::Profile;
+use crate::{
+    Profile,
+    device::{DeviceEntry, DeviceInfo, VirtualDeviceInfo},
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, BufWriter, Write},
+    ffi::OsString,
+    io::{self, BufRead, BufReader, BufWriter, Write},
     marker::PhantomData,
     os::{
         fd::AsRawFd,
         unix::{
-            fs::PermissionsExt,
+            ffi::{OsStrExt, OsStringExt},
+            fs::{FileTypeExt, PermissionsExt},
             net::{UnixListener, UnixStream},
         },
     },
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::LazyLock,
 };
 
-static PATH: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from("/run/clicker-rs/clicker-rs.sock"));
+pub static DEFAULT_SOCKET: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("/run/clicker-rs/clicker-rs.sock"));
+
+/// Where the daemon records the socket it listens on, so clients find a socket moved by the config.
+pub static SOCKET_RECORD: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("/run/clicker-rs/socket-path"));
+
+/// Records the socket the daemon listens on in [`SOCKET_RECORD`].
+pub fn record_socket(socket: &Path) -> anyhow::Result<()> {
+    let socket = std::path::absolute(socket)?;
+    if let Some(parent) = SOCKET_RECORD.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&*SOCKET_RECORD, socket.as_os_str().as_bytes())?;
+
+    // Readable by clients whatever the service's umask, access is controlled by the socket itself
+    let mut perms = std::fs::metadata(&*SOCKET_RECORD)?.permissions();
+    perms.set_mode(0o644);
+    std::fs::set_permissions(&*SOCKET_RECORD, perms)?;
+
+    Ok(())
+}
+
+/// The socket recorded by the running daemon, the default one when there is no record.
+pub fn socket_path() -> PathBuf {
+    std::fs::read(&*SOCKET_RECORD)
+        .ok()
+        .filter(|recorded| !recorded.is_empty())
+        .map(|recorded| PathBuf::from(OsString::from_vec(recorded)))
+        .unwrap_or_else(|| DEFAULT_SOCKET.clone())
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum IpcRequest {
-    SwitchProfile { name: String },
-    GetProfile { name: String },
+    SwitchProfile {
+        name: String,
+    },
+    GetProfile {
+        name: String,
+    },
     GetCurrentProfile,
     GetAllProfiles,
+    ListDevices,
+    GetVirtualDevice,
+    Reload,
+    /// Checks the daemon's own config file, other files are checked by the client.
+    Validate,
+    /// Adds a profile from its config fields, `persist` also writes it to the state file.
+    CreateProfile {
+        name: String,
+        fields: Map<String, Value>,
+        persist: bool,
+    },
+    /// Merges the given fields into a profile, a `null` field unsets it.
+    UpdateProfile {
+        name: String,
+        fields: Map<String, Value>,
+        persist: bool,
+    },
+    DeleteProfile {
+        name: String,
+        persist: bool,
+    },
+    Subscribe,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum IpcResponse {
     Profile(Profile),
     AllProfiles(Vec<Profile>),
+    Devices(Vec<DeviceEntry>),
+    VirtualDevice(VirtualDeviceInfo),
+    /// Problems found in a config, empty when it is valid.
+    Diagnostics(Vec<String>),
     Ok,
     Error(String),
 }
 
+/// Sent to subscribed clients whenever the daemon's state changes.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum IpcEvent {
+    DeviceAdded(DeviceInfo),
+    DeviceRemoved(DeviceInfo),
+    ConfigReloaded,
+    /// A profile was added, changed or removed over IPC.
+    ProfileChanged {
+        name: String,
+    },
+}
+
 pub struct Client;
 pub struct Server;
 
@@ -44,10 +122,12 @@ pub struct Ipc<T> {
 struct ServerData {
     listener: UnixListener,
     connections: HashMap<i32, UnixStream>,
+    subscribers: Vec<i32>,
 }
 
 struct ClientData {
     stream: UnixStream,
+    reader: BufReader<UnixStream>,
 }
 
 enum IpcInner {
@@ -56,11 +136,12 @@ enum IpcInner {
 }
 
 impl Ipc<Client> {
-    pub fn connect() -> anyhow::Result<Self> {
-        let stream = UnixStream::connect(&*PATH)?;
+    pub fn connect(path: &Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        let reader = BufReader::new(stream.try_clone()?);
 
         Ok(Self {
-            inner: IpcInner::Client(ClientData { stream }),
+            inner: IpcInner::Client(ClientData { stream, reader }),
             phantom: PhantomData,
         })
     }
@@ -86,9 +167,8 @@ impl Ipc<Client> {
         writer.flush()?;
 
         // Read response as JSON line
-        let mut reader = BufReader::new(&inner.stream);
         let mut response_line = String::new();
-        reader.read_line(&mut response_line)?;
+        inner.reader.read_line(&mut response_line)?;
 
         let response: IpcResponse = serde_json::from_str(response_line.trim())?;
         Ok(response)
@@ -109,10 +189,73 @@ impl Ipc<Client> {
     pub fn switch_profile(&mut self, name: String) -> anyhow::Result<IpcResponse> {
         self.send_request_and_receive_response(IpcRequest::SwitchProfile { name })
     }
+
+    pub fn request_devices(&mut self) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::ListDevices)
+    }
+
+    pub fn request_virtual_device(&mut self) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::GetVirtualDevice)
+    }
+
+    pub fn reload(&mut self) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::Reload)
+    }
+
+    pub fn validate(&mut self) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::Validate)
+    }
+
+    pub fn create_profile(
+        &mut self,
+        name: String,
+        fields: Map<String, Value>,
+        persist: bool,
+    ) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::CreateProfile {
+            name,
+            fields,
+            persist,
+        })
+    }
+
+    pub fn update_profile(
+        &mut self,
+        name: String,
+        fields: Map<String, Value>,
+        persist: bool,
+    ) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::UpdateProfile {
+            name,
+            fields,
+            persist,
+        })
+    }
+
+    pub fn delete_profile(&mut self, name: String, persist: bool) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::DeleteProfile { name, persist })
+    }
+
+    pub fn subscribe(&mut self) -> anyhow::Result<IpcResponse> {
+        self.send_request_and_receive_response(IpcRequest::Subscribe)
+    }
+
+    /// Blocks until the daemon sends the next event, requires a prior [`Self::subscribe`].
+    pub fn read_event(&mut self) -> anyhow::Result<IpcEvent> {
+        let inner = self.get_inner();
+
+        let mut event_line = String::new();
+        if inner.reader.read_line(&mut event_line)? == 0 {
+            return Err(anyhow::anyhow!("Connection closed"));
+        }
+
+        let event: IpcEvent = serde_json::from_str(event_line.trim())?;
+        Ok(event)
+    }
 }
 
 impl Ipc<Server> {
-    pub fn server() -> anyhow::Result<Self> {
+    pub fn server(path: &Path) -> anyhow::Result<Self> {
         if let Ok(output) = std::process::Command::new("pidof").arg("clickerd").output() {
             if output.status.success() {
                 let pids = String::from_utf8_lossy(&output.stdout);
@@ -122,25 +265,35 @@ impl Ipc<Server> {
             }
         }
 
-        if !PATH.exists() {
-            std::fs::create_dir_all(
-                PATH.parent()
-                    .ok_or(anyhow::anyhow!("Parent of {:#?} not found", PATH))?,
-            )?;
-        } else {
-            std::fs::remove_file(&*PATH)?;
+        // Only a stale socket is replaced, the path comes from the config and we run as root
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(anyhow::anyhow!(
+                    "{} exists and isn't a socket, refusing to replace it",
+                    path.display()
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                std::fs::create_dir_all(
+                    path.parent()
+                        .ok_or(anyhow::anyhow!("Parent of {:#?} not found", path))?,
+                )?;
+            }
+            Err(e) => return Err(e.into()),
         }
 
-        let listener = UnixListener::bind(&*PATH)?;
+        let listener = UnixListener::bind(path)?;
 
-        let mut perms = std::fs::metadata(&*PATH)?.permissions();
+        let mut perms = std::fs::metadata(path)?.permissions();
         perms.set_mode(0o660);
-        std::fs::set_permissions(&*PATH, perms)?;
+        std::fs::set_permissions(path, perms)?;
 
         Ok(Self {
             inner: IpcInner::Server(ServerData {
                 listener,
                 connections: HashMap::new(),
+                subscribers: Vec::new(),
             }),
             phantom: PhantomData,
         })
@@ -176,6 +329,51 @@ impl Ipc<Server> {
     pub fn remove_connection(&mut self, fd: &i32) {
         let inner = self.get_inner_mut();
         _ = inner.connections.remove(fd);
+        inner.subscribers.retain(|subscriber| subscriber != fd);
+    }
+
+    /// Makes the connection nonblocking, so a subscriber that stops reading can't stall the daemon.
+    pub fn subscribe(&mut self, fd: i32) -> anyhow::Result<()> {
+        let inner = self.get_inner_mut();
+        inner
+            .connections
+            .get(&fd)
+            .ok_or(anyhow::anyhow!("Connection not found"))?
+            .set_nonblocking(true)?;
+
+        if !inner.subscribers.contains(&fd) {
+            inner.subscribers.push(fd);
+        }
+
+        Ok(())
+    }
+
+    /// Sends an event to every subscriber, dropping the ones that fail or whose buffer is full.
+    pub fn broadcast(&mut self, event: &IpcEvent) -> anyhow::Result<()> {
+        let event = serde_json::to_string(event)?;
+
+        let inner = self.get_inner_mut();
+        let mut disconnected = Vec::new();
+        for fd in inner.subscribers.iter() {
+            let Some(conn) = inner.connections.get_mut(fd) else {
+                disconnected.push(*fd);
+                continue;
+            };
+
+            if conn
+                .write_all(format!("{event}\n").as_bytes())
+                .and_then(|_| conn.flush())
+                .is_err()
+            {
+                disconnected.push(*fd);
+            }
+        }
+
+        for fd in disconnected {
+            self.remove_connection(&fd);
+        }
+
+        Ok(())
     }
 
     pub fn get_listener(&self) -> &UnixListener {
@@ -0,0 +1,258 @@
+use evdev::KeyCode;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Highest key code the kernel defines, `KEY_MAX`.
+const KEY_MAX: u16 = 0x2ff;
+
+/// Friendly names, compared after lowercasing and dropping `_`, `-` and spaces.
+static ALIASES: &[(&str, KeyCode)] = &[
+    ("leftclick", KeyCode::BTN_LEFT),
+    ("rightclick", KeyCode::BTN_RIGHT),
+    ("middleclick", KeyCode::BTN_MIDDLE),
+    ("mouse1", KeyCode::BTN_LEFT),
+    ("mouse2", KeyCode::BTN_RIGHT),
+    ("mouse3", KeyCode::BTN_MIDDLE),
+    ("mouse4", KeyCode::BTN_SIDE),
+    ("mouse5", KeyCode::BTN_EXTRA),
+    ("ctrl", KeyCode::KEY_LEFTCTRL),
+    ("control", KeyCode::KEY_LEFTCTRL),
+    ("lctrl", KeyCode::KEY_LEFTCTRL),
+    ("rctrl", KeyCode::KEY_RIGHTCTRL),
+    ("shift", KeyCode::KEY_LEFTSHIFT),
+    ("lshift", KeyCode::KEY_LEFTSHIFT),
+    ("rshift", KeyCode::KEY_RIGHTSHIFT),
+    ("alt", KeyCode::KEY_LEFTALT),
+    ("lalt", KeyCode::KEY_LEFTALT),
+    ("ralt", KeyCode::KEY_RIGHTALT),
+    ("altgr", KeyCode::KEY_RIGHTALT),
+    ("super", KeyCode::KEY_LEFTMETA),
+    ("meta", KeyCode::KEY_LEFTMETA),
+    ("win", KeyCode::KEY_LEFTMETA),
+    ("escape", KeyCode::KEY_ESC),
+    ("return", KeyCode::KEY_ENTER),
+    ("del", KeyCode::KEY_DELETE),
+    ("ins", KeyCode::KEY_INSERT),
+    ("pgup", KeyCode::KEY_PAGEUP),
+    ("pgdn", KeyCode::KEY_PAGEDOWN),
+];
+
+/// A key as written in a config, either a name or a raw numeric code.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyRepr {
+    Name(String),
+    Code(u16),
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Names of every key code the kernel knows about.
+fn known_names() -> impl Iterator<Item = String> {
+    (0..=KEY_MAX)
+        .map(|code| format!("{:?}", KeyCode::new(code)))
+        .filter(|name| !name.starts_with("unknown"))
+}
+
+fn from_code(code: u16) -> Result<KeyCode, String> {
+    if code > KEY_MAX {
+        return Err(format!("key code {code} is out of range"));
+    }
+
+    Ok(KeyCode::new(code))
+}
+
+/// Resolves evdev names in any case, with or without their `KEY_`/`BTN_` prefix, aliases and hex codes.
+///
+/// Digits name the number row, `1` is `KEY_1`, decimal codes are only accepted as numbers.
+pub fn parse_key(name: &str) -> Result<KeyCode, String> {
+    if let Ok(key_code) = KeyCode::from_str(name) {
+        return Ok(key_code);
+    }
+
+    let trimmed = name.trim();
+    if let Some(hex) = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+    {
+        return u16::from_str_radix(hex, 16)
+            .map_err(|_| format!("invalid key code `{name}`"))
+            .and_then(from_code);
+    }
+    let upper = trimmed.to_uppercase();
+    for candidate in [
+        upper.clone(),
+        format!("KEY_{upper}"),
+        format!("BTN_{upper}"),
+    ] {
+        if let Ok(key_code) = KeyCode::from_str(&candidate) {
+            return Ok(key_code);
+        }
+    }
+
+    let normalized = normalize(trimmed);
+    if let Some((_, key_code)) = ALIASES.iter().find(|(alias, _)| *alias == normalized) {
+        return Ok(*key_code);
+    }
+
+    if let Ok(code) = trimmed.parse::<u16>() {
+        return Err(format!(
+            "ambiguous key `{name}`, write key codes as an unquoted number ({code}) or in hex ({code:#x})"
+        ));
+    }
+
+    match suggest(&normalized) {
+        Some(suggestion) => Err(format!(
+            "unknown key `{name}`, did you mean `{suggestion}`?"
+        )),
+        None => Err(format!("unknown key `{name}`")),
+    }
+}
+
+/// Canonical name of a key, its hex code when evdev has no name for it.
+pub fn key_name(key_code: KeyCode) -> String {
+    let name = format!("{key_code:?}");
+    if name.starts_with("unknown") {
+        format!("{:#x}", key_code.code())
+    } else {
+        name
+    }
+}
+
+/// Finds the closest known name or alias, if any is close enough to be a typo.
+fn suggest(normalized: &str) -> Option<String> {
+    let aliases = ALIASES.iter().map(|(alias, _)| alias.to_string());
+    known_names()
+        .chain(aliases)
+        .flat_map(|name| {
+            // Compare against the name with and without its prefix, `F8` is as valid as `KEY_F8`
+            let short = name
+                .strip_prefix("KEY_")
+                .or_else(|| name.strip_prefix("BTN_"))
+                .map(normalize);
+            [Some(normalize(&name)), short]
+                .into_iter()
+                .flatten()
+                .map(move |candidate| (distance(normalized, &candidate), name.clone()))
+        })
+        .filter(|(distance, _)| *distance <= (normalized.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// Edit distance between two strings, counting a swap of adjacent characters as one edit.
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = substitution.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+pub(crate) fn deserialize_key<'de, D>(d: D) -> Result<KeyCode, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match KeyRepr::deserialize(d)? {
+        KeyRepr::Name(name) => parse_key(&name),
+        KeyRepr::Code(code) => from_code(code),
+    }
+    .map_err(serde::de::Error::custom)
+}
+
+/// Wrapper to deserialize keys inside collections.
+#[derive(Deserialize)]
+pub(crate) struct Key(#[serde(deserialize_with = "deserialize_key")] pub KeyCode);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_names_in_any_case_and_without_prefix() {
+        assert_eq!(parse_key("KEY_F8"), Ok(KeyCode::KEY_F8));
+        assert_eq!(parse_key("key_f8"), Ok(KeyCode::KEY_F8));
+        assert_eq!(parse_key("f8"), Ok(KeyCode::KEY_F8));
+        assert_eq!(parse_key("left"), Ok(KeyCode::KEY_LEFT));
+        assert_eq!(parse_key("btn_side"), Ok(KeyCode::BTN_SIDE));
+    }
+
+    #[test]
+    fn resolves_aliases() {
+        assert_eq!(parse_key("LeftClick"), Ok(KeyCode::BTN_LEFT));
+        assert_eq!(parse_key("left-click"), Ok(KeyCode::BTN_LEFT));
+        assert_eq!(parse_key("Mouse4"), Ok(KeyCode::BTN_SIDE));
+        assert_eq!(parse_key("Ctrl"), Ok(KeyCode::KEY_LEFTCTRL));
+    }
+
+    #[test]
+    fn resolves_digits_to_the_number_row() {
+        assert_eq!(parse_key("0"), Ok(KeyCode::KEY_0));
+        assert_eq!(parse_key("1"), Ok(KeyCode::KEY_1));
+        assert_eq!(parse_key("9"), Ok(KeyCode::KEY_9));
+    }
+
+    #[test]
+    fn accepts_codes_only_as_numbers_or_hex() {
+        assert_eq!(parse_key("0x1e"), Ok(KeyCode::KEY_A));
+        assert_eq!(parse_key("0X1E"), Ok(KeyCode::KEY_A));
+        assert!(parse_key("0x300").is_err());
+        assert_eq!(
+            parse_key("30"),
+            Err(
+                "ambiguous key `30`, write key codes as an unquoted number (30) or in hex (0x1e)"
+                    .to_string()
+            )
+        );
+
+        let key: Key = serde_json::from_value(serde_json::json!(30)).unwrap();
+        assert_eq!(key.0, KeyCode::KEY_A);
+        assert!(serde_json::from_value::<Key>(serde_json::json!(0x300)).is_err());
+    }
+
+    #[test]
+    fn unnamed_codes_round_trip() {
+        let name = key_name(KeyCode::new(0x2fe));
+        assert_eq!(name, "0x2fe");
+        assert_eq!(parse_key(&name), Ok(KeyCode::new(0x2fe)));
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        assert_eq!(
+            parse_key("KEY_F88"),
+            Err("unknown key `KEY_F88`, did you mean `KEY_F8`?".to_string())
+        );
+        assert_eq!(
+            parse_key("lfetclick"),
+            Err("unknown key `lfetclick`, did you mean `leftclick`?".to_string())
+        );
+        assert_eq!(
+            parse_key("nonsense"),
+            Err("unknown key `nonsense`".to_string())
+        );
+    }
+}
@@ -1,24 +1,49 @@
+pub mod device;
 pub mod ipc;
+pub mod keys;
 
+use device::DeviceMatch;
 use evdev::KeyCode;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Profile {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Fields taken from the profiles this one extends, filled in when the config is loaded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inherited: Vec<String>,
 
+    /// Keys held to activate the profile, an empty list keeps it always active.
     #[serde(
-        serialize_with = "serialize_activation_keys",
-        deserialize_with = "deserialize_activation_keys"
+        serialize_with = "serialize_optional_keys",
+        deserialize_with = "deserialize_optional_keys",
+        default,
+        skip_serializing_if = "Option::is_none"
     )]
-    pub activation_keys: Vec<KeyCode>,
+    pub activation_keys: Option<Vec<KeyCode>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub activation_devices: Vec<DeviceMatch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activation_sequence: Option<KeySequence>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_tap: Option<DoubleTap>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub long_press: Option<LongPress>,
     #[serde(
         serialize_with = "serialize_repeat_key",
         deserialize_with = "deserialize_repeat_key",
         default = "default_repeat_key"
     )]
     pub repeat_key: KeyCode,
+    #[serde(
+        serialize_with = "serialize_trigger_key",
+        deserialize_with = "deserialize_trigger_key",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub trigger_key: Option<KeyCode>,
 
     pub cps: Cps,
     #[serde(default = "default_toggle")]
@@ -28,6 +53,16 @@ pub struct Profile {
 
     #[serde(default = "default_hold_to_click")]
     pub hold_to_click: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_on_user_input: Option<StopOnUserInput>,
+}
+
+impl Profile {
+    /// Key that starts clicking while held, defaults to the repeat key.
+    pub fn trigger(&self) -> KeyCode {
+        self.trigger_key.unwrap_or(self.repeat_key)
+    }
 }
 
 fn default_toggle() -> bool {
@@ -45,6 +80,63 @@ pub struct Cps {
     pub std_dev: f32,
 }
 
+/// Keys pressed one after another, each within `timeout` milliseconds of the previous one.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct KeySequence {
+    #[serde(
+        serialize_with = "serialize_activation_keys",
+        deserialize_with = "deserialize_activation_keys"
+    )]
+    pub keys: Vec<KeyCode>,
+    #[serde(default = "default_sequence_timeout")]
+    pub timeout: u64,
+}
+
+fn default_sequence_timeout() -> u64 {
+    500
+}
+
+/// Pressing `key` twice within `interval` milliseconds toggles the profile.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct DoubleTap {
+    #[serde(
+        serialize_with = "serialize_repeat_key",
+        deserialize_with = "deserialize_repeat_key"
+    )]
+    pub key: KeyCode,
+    #[serde(default = "default_double_tap_interval")]
+    pub interval: u64,
+}
+
+fn default_double_tap_interval() -> u64 {
+    300
+}
+
+/// Holding `key` for `duration` milliseconds toggles the profile.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct LongPress {
+    #[serde(
+        serialize_with = "serialize_repeat_key",
+        deserialize_with = "deserialize_repeat_key"
+    )]
+    pub key: KeyCode,
+    #[serde(default = "default_long_press_duration")]
+    pub duration: u64,
+}
+
+fn default_long_press_duration() -> u64 {
+    800
+}
+
+/// Stops a running clicker once the physical mouse moved `motion` units or `keys` non-trigger keys were pressed.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct StopOnUserInput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keys: Option<u32>,
+}
+
 fn default_std_dev() -> f32 {
     1.5
 }
@@ -57,31 +149,59 @@ fn serialize_repeat_key<S>(key: &KeyCode, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    format!("{key:?}").serialize(s)
+    keys::key_name(*key).serialize(s)
 }
 
 fn deserialize_repeat_key<'de, D>(d: D) -> Result<KeyCode, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let str = String::deserialize(d)?;
-    KeyCode::from_str(&str).map_err(serde::de::Error::custom)
+    keys::deserialize_key(d)
+}
+
+fn serialize_trigger_key<S>(key: &Option<KeyCode>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    key.map(keys::key_name).serialize(s)
+}
+
+fn deserialize_trigger_key<'de, D>(d: D) -> Result<Option<KeyCode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<keys::Key>::deserialize(d)?.map(|key| key.0))
 }
 
-fn serialize_activation_keys<S>(keys: &Vec<KeyCode>, s: S) -> Result<S::Ok, S::Error>
+pub fn serialize_activation_keys<S>(keys: &Vec<KeyCode>, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let strs: Vec<String> = keys.iter().map(|k| format!("{k:?}")).collect();
+    let strs: Vec<String> = keys.iter().map(|k| keys::key_name(*k)).collect();
     strs.serialize(s)
 }
 
-fn deserialize_activation_keys<'de, D>(d: D) -> Result<Vec<KeyCode>, D::Error>
+pub fn deserialize_activation_keys<'de, D>(d: D) -> Result<Vec<KeyCode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let keys = Vec::<keys::Key>::deserialize(d)?;
+    Ok(keys.into_iter().map(|key| key.0).collect())
+}
+
+fn serialize_optional_keys<S>(keys: &Option<Vec<KeyCode>>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    keys.as_ref()
+        .map(|keys| keys.iter().map(|k| keys::key_name(*k)).collect::<Vec<_>>())
+        .serialize(s)
+}
+
+fn deserialize_optional_keys<'de, D>(d: D) -> Result<Option<Vec<KeyCode>>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let strs = Vec::<String>::deserialize(d)?;
-    strs.into_iter()
-        .map(|s| KeyCode::from_str(&s).map_err(serde::de::Error::custom))
-        .collect()
+    let keys = Option::<Vec<keys::Key>>::deserialize(d)?;
+    Ok(keys.map(|keys| keys.into_iter().map(|key| key.0).collect()))
 }
@@ -1,9 +1,16 @@
 use anyhow::Context;
-use clap::Parser;
-use common::Profile;
-use common::ipc::{Ipc, IpcResponse};
-use serde_json::to_string_pretty;
-use std::io::{self, Write};
+use clap::{Parser, Subcommand};
+use common::ipc::{self, Client, Ipc, IpcEvent, IpcResponse};
+use common::{
+    Profile,
+    device::{DeviceEntry, VirtualDeviceInfo},
+};
+use serde_json::{Map, Value, to_string_pretty};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "clicker", about = "Control the clicker-rs daemon")]
@@ -14,6 +21,10 @@ pub struct Args {
     /// Output in JSON format
     #[arg(long, global = true)]
     pub json: bool,
+
+    /// Path to the daemon's IPC socket, defaults to the one the running daemon recorded
+    #[arg(long, global = true, value_name = "FILE")]
+    pub socket: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
@@ -32,6 +43,56 @@ pub enum Cli {
     },
     /// Show the currently active profile
     Current,
+    /// List the input devices the daemon has opened
+    Devices,
+    /// Show the identity of the daemon's virtual device
+    VirtualDevice,
+    /// Reload the daemon's config file
+    Reload,
+    /// Check a config file for errors as the current user, defaults to the daemon's config
+    Validate {
+        #[arg(help = "Path to the config file to check")]
+        path: Option<PathBuf>,
+    },
+    /// Print daemon events as they happen
+    Events,
+    /// Add, change or remove profiles at runtime
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// Add a new profile
+    Add {
+        #[arg(help = "Name of the new profile")]
+        name: String,
+        #[arg(help = "Fields as `key=value`, nested with dots like `cps.target=20`")]
+        fields: Vec<String>,
+        /// Save the profile to the daemon's state file
+        #[arg(long)]
+        persist: bool,
+    },
+    /// Change fields of a profile, `key=null` unsets one
+    Set {
+        #[arg(help = "Name of the profile to change")]
+        name: String,
+        #[arg(help = "Fields as `key=value`, nested with dots like `cps.target=20`")]
+        fields: Vec<String>,
+        /// Save the change to the daemon's state file
+        #[arg(long)]
+        persist: bool,
+    },
+    /// Remove a profile
+    Rm {
+        #[arg(help = "Name of the profile to remove")]
+        name: String,
+        /// Save the removal to the daemon's state file
+        #[arg(long)]
+        persist: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -73,7 +134,7 @@ fn format_profile_pretty(profile: &Profile) -> String {
 
     output.push_str(&format!(
         "  {:?} → {} CPS{}{}\n",
-        profile.activation_keys,
+        profile.activation_keys.as_deref().unwrap_or_default(),
         profile.cps.target,
         if profile.toggle { " (toggle)" } else { "" },
         jitter_display
@@ -82,34 +143,226 @@ fn format_profile_pretty(profile: &Profile) -> String {
     output
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let mut ipc = Ipc::connect().context("Failed to connect to IPC")?;
-    let mut stdout = io::stdout();
-    let mut stderr = io::stderr();
+/// Every field of a single profile, marking the ones inherited through `extends`.
+fn format_profile_details(profile: &Profile) -> anyhow::Result<String> {
+    let mut output = String::new();
 
-    let response: IpcResponse = match args.command {
-        Cli::Profiles => ipc.request_all_profiles()?,
-        Cli::Show { ref name } => ipc.request_profile(name.to_owned())?,
-        Cli::Select { ref name } => ipc.switch_profile(name.to_owned())?,
-        Cli::Current => ipc.request_current_profile()?,
+    output.push_str(&format!("\x1b[34m{}\x1b[0m", profile.name));
+    if let Some(extends) = profile.extends.as_ref() {
+        output.push_str(&format!(" (extends '{extends}')"));
+    }
+    output.push('\n');
+
+    let serde_json::Value::Object(fields) = serde_json::to_value(profile)? else {
+        return Ok(output);
     };
+    for (field, value) in fields.iter() {
+        if matches!(field.as_str(), "name" | "extends" | "inherited") {
+            continue;
+        }
+
+        let marker = if profile.inherited.contains(field) {
+            " \x1b[2m(inherited)\x1b[0m"
+        } else if profile
+            .inherited
+            .iter()
+            .any(|inherited| inherited.starts_with(&format!("{field}.")))
+        {
+            " \x1b[2m(partly inherited)\x1b[0m"
+        } else {
+            ""
+        };
+        output.push_str(&format!("  {field}: {value}{marker}\n"));
+    }
+
+    Ok(output.trim_end().to_string())
+}
+
+fn format_devices_pretty(devices: &[DeviceEntry]) -> String {
+    if devices.is_empty() {
+        return "No devices found.".to_string();
+    }
+
+    let mut output = String::new();
+    for device in devices {
+        let info = &device.info;
+        output.push_str(&format!(
+            "\x1b[34m{}\x1b[0m ({})\n",
+            info.name,
+            info.path.display()
+        ));
+        output.push_str(&format!("  id: {}", info.id()));
+        if let Some(phys) = info.phys.as_ref() {
+            output.push_str(&format!(", phys: {phys}"));
+        }
+        output.push_str(&format!(
+            "\n  roles: {:?}, grabbed: {}, used: {}\n",
+            info.roles,
+            if device.grabbed { "yes" } else { "no" },
+            if device.used { "yes" } else { "no" }
+        ));
+    }
+
+    output.trim_end().to_string()
+}
+
+fn format_virtual_device_pretty(device: &VirtualDeviceInfo) -> String {
+    let dev_nodes = device
+        .dev_nodes
+        .iter()
+        .map(|dev_node| dev_node.display().to_string())
+        .collect::<Vec<_>>();
+
+    format!(
+        "\x1b[34m{}\x1b[0m\n  id: {:04x}:{:04x}, version: {:#x}, bus: {}\n  devnodes: {}",
+        device.name,
+        device.vendor,
+        device.product,
+        device.version,
+        device.bustype,
+        dev_nodes.join(", ")
+    )
+}
+
+fn format_event_pretty(event: &IpcEvent) -> String {
+    match event {
+        IpcEvent::DeviceAdded(device) => format!(
+            "\x1b[32m+\x1b[0m {} ({})",
+            device.name,
+            device.path.display()
+        ),
+        IpcEvent::DeviceRemoved(device) => format!(
+            "\x1b[31m-\x1b[0m {} ({})",
+            device.name,
+            device.path.display()
+        ),
+        IpcEvent::ConfigReloaded => "\x1b[34m↻\x1b[0m config reloaded".to_string(),
+        IpcEvent::ProfileChanged { name } => format!("\x1b[34m~\x1b[0m profile '{name}' changed"),
+    }
+}
+
+/// Parses `key=value` pairs into profile fields, values that aren't valid JSON are taken as strings.
+fn parse_fields(fields: &[String]) -> anyhow::Result<Map<String, Value>> {
+    let mut parsed = Map::new();
+
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or(anyhow::anyhow!("Expected `key=value`, got `{field}`"))?;
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+
+        let mut segments = key.split('.').collect::<Vec<_>>();
+        let last = segments
+            .pop()
+            .filter(|last| !last.is_empty())
+            .ok_or(anyhow::anyhow!("Empty field name in `{field}`"))?;
+        let mut object = &mut parsed;
+        for segment in segments {
+            let entry = object
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()));
+            let Value::Object(nested) = entry else {
+                return Err(anyhow::anyhow!(
+                    "`{segment}` is set to a value and has fields"
+                ));
+            };
+            object = nested;
+        }
+        object.insert(last.to_string(), value);
+    }
+
+    Ok(parsed)
+}
+
+/// Checks a config file with `clickerd --check-config`, so it is read with the caller's permissions.
+fn check_config(path: &Path) -> anyhow::Result<IpcResponse> {
+    // Installed next to this binary, otherwise looked up in `PATH`
+    let clickerd = std::env::current_exe()
+        .map(|exe| exe.with_file_name("clickerd"))
+        .ok()
+        .filter(|clickerd| clickerd.exists())
+        .unwrap_or_else(|| PathBuf::from("clickerd"));
+
+    let output = Command::new(&clickerd)
+        .arg("--check-config")
+        .arg(path)
+        .arg("--json")
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run {}", clickerd.display()))?;
+    let diagnostics = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Failed to check {}", path.display()))?;
+
+    Ok(IpcResponse::Diagnostics(diagnostics))
+}
+
+fn watch_events(ipc: &mut Ipc<Client>, json: bool) -> anyhow::Result<()> {
+    let mut stdout = io::stdout();
+
+    if let IpcResponse::Error(err) = ipc.subscribe()? {
+        return Err(anyhow::anyhow!(err));
+    }
+
+    loop {
+        let event = ipc.read_event()?;
+        if json {
+            writeln!(stdout, "{}", serde_json::to_string(&event)?)?;
+        } else {
+            writeln!(stdout, "{}", format_event_pretty(&event))?;
+        }
+        stdout.flush()?;
+    }
+}
+
+fn print_response(response: IpcResponse, json: bool) -> anyhow::Result<()> {
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
 
     match response {
         IpcResponse::Ok => writeln!(stdout, "Operation successful")?,
         IpcResponse::Error(err) => writeln!(stderr, "Error: {}", err)?,
         IpcResponse::AllProfiles(profiles) => {
-            if args.json {
+            if json {
                 writeln!(stdout, "{}", to_string_pretty(&profiles)?)?;
             } else {
                 writeln!(stdout, "{}", format_profiles_pretty(&profiles))?;
             }
         }
+        IpcResponse::Devices(devices) => {
+            if json {
+                writeln!(stdout, "{}", to_string_pretty(&devices)?)?;
+            } else {
+                writeln!(stdout, "{}", format_devices_pretty(&devices))?;
+            }
+        }
+        IpcResponse::VirtualDevice(device) => {
+            if json {
+                writeln!(stdout, "{}", to_string_pretty(&device)?)?;
+            } else {
+                writeln!(stdout, "{}", format_virtual_device_pretty(&device))?;
+            }
+        }
+        IpcResponse::Diagnostics(diagnostics) => {
+            if json {
+                writeln!(stdout, "{}", to_string_pretty(&diagnostics)?)?;
+            } else if diagnostics.is_empty() {
+                writeln!(stdout, "Config is valid")?;
+            } else {
+                for diagnostic in diagnostics.iter() {
+                    writeln!(stderr, "{diagnostic}")?;
+                }
+            }
+
+            if !diagnostics.is_empty() {
+                stdout.flush()?;
+                std::process::exit(1);
+            }
+        }
         IpcResponse::Profile(profile) => {
-            if args.json {
+            if json {
                 writeln!(stdout, "{}", to_string_pretty(&profile)?)?;
             } else {
-                writeln!(stdout, "{}", format_profile_pretty(&profile))?;
+                writeln!(stdout, "{}", format_profile_details(&profile)?)?;
             }
         }
     }
@@ -117,3 +370,43 @@ fn main() -> anyhow::Result<()> {
     stdout.flush()?;
     Ok(())
 }
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let socket = args.socket.clone().unwrap_or_else(ipc::socket_path);
+
+    if let Cli::Validate { path: Some(path) } = &args.command {
+        let response = check_config(path)?;
+        return print_response(response, args.json);
+    }
+
+    let mut ipc = Ipc::connect(&socket).context("Failed to connect to IPC")?;
+    let response: IpcResponse = match args.command {
+        Cli::Profiles => ipc.request_all_profiles()?,
+        Cli::Show { ref name } => ipc.request_profile(name.to_owned())?,
+        Cli::Select { ref name } => ipc.switch_profile(name.to_owned())?,
+        Cli::Current => ipc.request_current_profile()?,
+        Cli::Devices => ipc.request_devices()?,
+        Cli::VirtualDevice => ipc.request_virtual_device()?,
+        Cli::Reload => ipc.reload()?,
+        Cli::Validate { .. } => ipc.validate()?,
+        Cli::Events => return watch_events(&mut ipc, args.json),
+        Cli::Profile { ref command } => match command {
+            ProfileCommand::Add {
+                name,
+                fields,
+                persist,
+            } => ipc.create_profile(name.to_owned(), parse_fields(fields)?, *persist)?,
+            ProfileCommand::Set {
+                name,
+                fields,
+                persist,
+            } => ipc.update_profile(name.to_owned(), parse_fields(fields)?, *persist)?,
+            ProfileCommand::Rm { name, persist } => {
+                ipc.delete_profile(name.to_owned(), *persist)?
+            }
+        },
+    };
+
+    print_response(response, args.json)
+}
@@ -1,54 +1,317 @@
-use common::Profile;
-use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use crate::{overlay::Overlay, validate};
+use common::{
+    Profile,
+    device::{DeviceInfo, DeviceMatch},
+};
+use evdev::{BusType, KeyCode};
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[derive(Deserialize, Debug, Default)]
 pub struct Config {
     #[serde(default)]
     pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub grab: Grab,
+    #[serde(default)]
+    pub devices: DeviceFilter,
+    #[serde(default)]
+    pub virtual_device: VirtualDeviceConfig,
+    #[serde(default)]
+    pub settings: Settings,
+    /// Only devices assigned to this seat are used, any seat when unset.
+    #[serde(default)]
+    pub seat: Option<String>,
+    /// The config file as it was read, profile changes are validated against it instead of the file on disk.
+    #[serde(skip)]
+    pub raw: serde_json::Value,
 }
 
+/// Daemon wide settings, command line flags take precedence over them.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Profile selected at startup, `null` starts without one.
+    #[serde(default = "default_profile")]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_log_level")]
+    pub log_level: Option<LevelFilter>,
+    /// File profiles changed over IPC are persisted to, merged over the config when loading.
+    #[serde(default)]
+    pub state: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_profile: default_profile(),
+            socket: None,
+            log_level: None,
+            state: None,
+        }
+    }
+}
+
+fn default_profile() -> Option<String> {
+    Some("default".to_string())
+}
+
+fn deserialize_log_level<'de, D>(d: D) -> Result<Option<LevelFilter>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(d)?
+        .map(|str| LevelFilter::from_str(&str).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Identity of the uinput device clicks are emitted from, so input rules can match it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct VirtualDeviceConfig {
+    #[serde(default = "default_virtual_device_name")]
+    pub name: String,
+    #[serde(default = "default_vendor")]
+    pub vendor: u16,
+    #[serde(default = "default_product")]
+    pub product: u16,
+    #[serde(default = "default_version")]
+    pub version: u16,
+    #[serde(default = "default_bustype", deserialize_with = "deserialize_bustype")]
+    pub bustype: BusType,
+}
+
+impl Default for VirtualDeviceConfig {
+    fn default() -> Self {
+        Self {
+            name: default_virtual_device_name(),
+            vendor: default_vendor(),
+            product: default_product(),
+            version: default_version(),
+            bustype: default_bustype(),
+        }
+    }
+}
+
+fn default_virtual_device_name() -> String {
+    "clicker-rs".to_string()
+}
+
+fn default_vendor() -> u16 {
+    0x1234
+}
+
+fn default_product() -> u16 {
+    0x5678
+}
+
+fn default_version() -> u16 {
+    0x111
+}
+
+fn default_bustype() -> BusType {
+    BusType::BUS_USB
+}
+
+fn deserialize_bustype<'de, D>(d: D) -> Result<BusType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str = String::deserialize(d)?;
+    BusType::from_str(&str).map_err(serde::de::Error::custom)
+}
+
+/// Devices the daemon may open, an empty `allow` list allows every device that isn't denied.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct DeviceFilter {
+    #[serde(default)]
+    pub allow: Vec<DeviceMatch>,
+    #[serde(default)]
+    pub deny: Vec<DeviceMatch>,
+}
+
+impl DeviceFilter {
+    pub fn permits(&self, device: &DeviceInfo) -> bool {
+        let allowed = self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|device_match| device_match.matches(device));
+        let denied = self
+            .deny
+            .iter()
+            .any(|device_match| device_match.matches(device));
+
+        allowed && !denied
+    }
+}
+
+/// Devices grabbed exclusively by the daemon, their events are re-emitted without the trigger `keys`.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Grab {
+    #[serde(default)]
+    pub devices: Vec<DeviceMatch>,
+    #[serde(default, deserialize_with = "common::deserialize_activation_keys")]
+    pub keys: Vec<KeyCode>,
+}
+
+/// Config file formats in the order they are searched for, chosen by extension when loading.
+static CONFIG_EXTENSIONS: [&str; 3] = ["nix", "toml", "json"];
+
 impl Config {
-    pub fn load(path: Option<PathBuf>) -> anyhow::Result<Config> {
-        let config_path = if let Some(path) = path {
-            path
-        } else {
-            Self::path()?
-        };
+    /// Whether both configs open and grab the same set of devices.
+    pub fn same_devices(&self, other: &Config) -> bool {
+        self.devices == other.devices && self.seat == other.seat && self.grab == other.grab
+    }
+
+    pub fn load(config_path: &Path, overlay: &Overlay) -> anyhow::Result<Config> {
+        Self::check(config_path, overlay)
+            .map_err(|diagnostics| anyhow::anyhow!(diagnostics.join("\n")))
+    }
+
+    /// Loads and validates a config, collecting every problem found in it.
+    pub fn check(config_path: &Path, overlay: &Overlay) -> Result<Config, Vec<String>> {
+        Self::check_raw(Self::read(config_path)?, overlay)
+    }
 
-        let nix_code = fs::read_to_string(&config_path)?;
-        let config: Config = tvix_serde::from_str(&nix_code)
-            .map_err(|e| anyhow::anyhow!("tvix_serde failed: {e:?}"))?;
+    /// Validates a config file read earlier, with the persisted state and `overlay` merged over it.
+    pub fn check_raw(raw: serde_json::Value, overlay: &Overlay) -> Result<Config, Vec<String>> {
+        let mut config = validate::deserialize(Self::merge(&raw, overlay)?)?;
+        config.raw = raw;
 
         Ok(config)
     }
 
+    /// Reads a config file in any of the supported formats.
+    pub fn read(config_path: &Path) -> Result<serde_json::Value, Vec<String>> {
+        let contents = fs::read_to_string(config_path)
+            .map_err(|e| vec![format!("Failed to read {}: {e}", config_path.display())])?;
+
+        // Every format is first read into a JSON value, so all of them share the same diagnostics
+        match config_path.extension().and_then(OsStr::to_str) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| vec![format!("{e}")]),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| vec![format!("{e}")]),
+            _ => tvix_serde::from_str(&contents)
+                .map_err(|e| vec![format!("tvix_serde failed: {e:?}")]),
+        }
+    }
+
+    /// A config file's value with the persisted state and then `overlay` merged over its profiles.
+    pub fn merge(
+        raw: &serde_json::Value,
+        overlay: &Overlay,
+    ) -> Result<serde_json::Value, Vec<String>> {
+        let mut value = raw.clone();
+        if let Some(state) = value
+            .get("settings")
+            .and_then(|settings| settings.get("state"))
+            .and_then(serde_json::Value::as_str)
+        {
+            let state = Overlay::load(Path::new(state))
+                .map_err(|e| vec![format!("Failed to read state file {state}: {e}")])?;
+            state.apply(&mut value);
+        }
+        overlay.apply(&mut value);
+
+        Ok(value)
+    }
+
     pub fn path() -> anyhow::Result<PathBuf> {
         let config_dir = PathBuf::from("/etc");
 
-        let config_dir_first = config_dir.join("clicker-rs.nix");
-        if config_dir_first.exists() {
-            log::info!("Configuration found at {}", config_dir_first.display());
-            return Ok(config_dir_first);
-        } else {
-            log::warn!("Configuration not found at {}", config_dir_first.display());
+        let candidates = CONFIG_EXTENSIONS
+            .iter()
+            .map(|extension| config_dir.join(format!("clicker-rs.{extension}")))
+            .chain(CONFIG_EXTENSIONS.iter().map(|extension| {
+                config_dir
+                    .join("clicker-rs")
+                    .join(format!("default.{extension}"))
+            }))
+            .collect::<Vec<_>>();
+
+        if let Some(config_path) = candidates.iter().find(|candidate| candidate.exists()) {
+            log::info!("Configuration found at {}", config_path.display());
+            return Ok(config_path.clone());
         }
 
-        let config_dir_second = config_dir.join("clicker-rs").join("default.nix");
-        if config_dir_second.exists() {
-            log::info!("Configuration found at {}", config_dir_second.display());
-            Ok(config_dir_second)
-        } else {
-            log::error!(
-                "Configuration not found at {} or {}",
-                config_dir_first.display(),
-                config_dir_second.display()
-            );
-            Err(anyhow::anyhow!(
-                "Configuration not found at {} or {}",
-                config_dir_first.display(),
-                config_dir_second.display()
-            ))
+        let searched = candidates
+            .iter()
+            .map(|candidate| candidate.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        log::error!("Configuration not found at any of {searched}");
+        Err(anyhow::anyhow!(
+            "Configuration not found at any of {searched}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn device(name: &str, path: &str) -> DeviceInfo {
+        DeviceInfo {
+            path: PathBuf::from(path),
+            name: name.to_string(),
+            vendor: 0x046d,
+            product: 0xc52b,
+            phys: Some("usb-0000:00:14.0-2/input0".to_string()),
+            roles: Vec::new(),
         }
     }
+
+    fn filter(value: serde_json::Value) -> DeviceFilter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_permits_every_device() {
+        assert!(DeviceFilter::default().permits(&device("Keyboard", "/dev/input/event3")));
+    }
+
+    #[test]
+    fn allow_list_permits_only_listed_devices() {
+        let filter = filter(json!({ "allow": [{ "name": "Macro Pad" }] }));
+
+        assert!(filter.permits(&device("Macro Pad", "/dev/input/event3")));
+        assert!(!filter.permits(&device("Keyboard", "/dev/input/event4")));
+    }
+
+    #[test]
+    fn deny_takes_precedence_over_allow() {
+        let filter = filter(json!({
+            "allow": [{ "id": "046d:c52b" }],
+            "deny": [{ "devnode": "/dev/input/event1*" }],
+        }));
+
+        assert!(filter.permits(&device("Receiver", "/dev/input/event3")));
+        assert!(!filter.permits(&device("Receiver", "/dev/input/event12")));
+    }
+
+    #[test]
+    fn every_set_field_must_match() {
+        let filter = filter(json!({
+            "deny": [{ "name": "Receiver", "phys": "usb-0000:00:14.0-2/input0" }],
+        }));
+
+        assert!(!filter.permits(&device("Receiver", "/dev/input/event3")));
+        assert!(filter.permits(&device("Keyboard", "/dev/input/event3")));
+    }
+
+    #[test]
+    fn invalid_devnode_glob_is_rejected() {
+        let result = serde_json::from_value::<DeviceFilter>(json!({
+            "deny": [{ "devnode": "/dev/input/event[" }],
+        }));
+
+        assert!(result.is_err_and(|e| e.to_string().contains("invalid glob pattern")));
+    }
 }
@@ -1,65 +1,289 @@
-use evdev::Device;
+use crate::config::Config;
+use calloop::RegistrationToken;
+use common::device::{DeviceInfo, DeviceRole};
+use evdev::{KeyCode, RelativeAxisCode, raw_stream::RawDevice, uinput::VirtualDevice};
 use std::{
-    fs,
+    ffi::OsStr,
+    fs, io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
-pub struct KeyboardDevices(Vec<Arc<Mutex<Device>>>);
+/// Device nodes of the virtual devices created by the daemon itself.
+#[derive(Clone, Default)]
+pub struct OwnDevices(Arc<Mutex<Vec<PathBuf>>>);
 
-impl KeyboardDevices {
-    pub fn try_new() -> anyhow::Result<Self> {
-        let devices = fs::read_dir("/dev/input")?
-            .filter_map(|entry| entry.map(|entry| entry.path()).ok())
-            .filter(|path| path.to_string_lossy().contains("event"))
-            .filter_map(|path| Device::open(&path).ok())
-            .filter(|device| {
-                device
-                    .supported_keys()
-                    .is_some_and(|keys| keys.contains(evdev::KeyCode::KEY_A))
-            })
-            .map(|device| {
-                if let Some(name) = device.name() {
-                    log::info!("Keyboard registered {name}");
-                }
-                Arc::new(Mutex::new(device))
-            })
-            .collect::<Vec<_>>();
-
-        Ok(Self(devices))
-    }
-
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Arc<Mutex<Device>>> {
-        self.0.iter_mut()
+impl OwnDevices {
+    pub fn register(&self, virtual_device: &mut VirtualDevice) -> anyhow::Result<Vec<PathBuf>> {
+        let dev_nodes = virtual_device
+            .enumerate_dev_nodes_blocking()?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.0.lock().unwrap().extend(dev_nodes.iter().cloned());
+
+        Ok(dev_nodes)
+    }
+
+    pub fn unregister(&self, dev_nodes: &[PathBuf]) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|dev_node| !dev_nodes.contains(dev_node));
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|dev_node| dev_node == path)
+    }
+}
+
+pub struct InputDevice {
+    pub info: Arc<DeviceInfo>,
+    pub registration_token: RegistrationToken,
+    pub grabbed: bool,
+}
+
+/// Classifies a device by its full capability set, a device can play several roles at once.
+fn classify(device: &RawDevice) -> Vec<DeviceRole> {
+    let Some(keys) = device.supported_keys() else {
+        return Vec::new();
+    };
+    let has_relative_motion = device.supported_relative_axes().is_some_and(|axes| {
+        axes.contains(RelativeAxisCode::REL_X) && axes.contains(RelativeAxisCode::REL_Y)
+    });
+
+    let mut roles = Vec::new();
+    if keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_Z) {
+        roles.push(DeviceRole::Keyboard);
+    }
+    if keys.contains(KeyCode::KEY_KP0) && keys.contains(KeyCode::KEY_KP9) {
+        roles.push(DeviceRole::Keypad);
+    }
+    if keys.contains(KeyCode::BTN_LEFT)
+        || keys.contains(KeyCode::BTN_RIGHT)
+        || keys.contains(KeyCode::BTN_MIDDLE)
+        || (has_relative_motion && keys.iter().any(is_mouse_button))
+    {
+        roles.push(DeviceRole::Mouse);
+    }
+    if keys.iter().any(is_gamepad_button) {
+        roles.push(DeviceRole::Gamepad);
+    }
+    if roles.is_empty() && keys.iter().next().is_some() {
+        roles.push(DeviceRole::Buttons);
+    }
+
+    roles
+}
+
+fn is_mouse_button(key: KeyCode) -> bool {
+    (KeyCode::BTN_LEFT.code()..KeyCode::BTN_TRIGGER.code()).contains(&key.code())
+}
+
+fn is_gamepad_button(key: KeyCode) -> bool {
+    (KeyCode::BTN_TRIGGER.code()..=KeyCode::BTN_THUMBR.code()).contains(&key.code())
+        || (KeyCode::BTN_TRIGGER_HAPPY1.code()..=KeyCode::BTN_TRIGGER_HAPPY40.code())
+            .contains(&key.code())
+}
+
+fn set_nonblocking(device: &RawDevice) -> io::Result<()> {
+    let fd = device.as_raw_fd();
+
+    // SAFETY: F_GETFL and F_SETFL only touch the flags of a descriptor we own
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Describes an event node from sysfs, so it can be filtered without opening it.
+fn sysfs_info(path: &Path) -> DeviceInfo {
+    let sysfs_dir = path
+        .file_name()
+        .map(|name| Path::new(SYSFS_INPUT_DIR).join(name).join("device"))
+        .unwrap_or_default();
+    let read = |attribute: &str| {
+        fs::read_to_string(sysfs_dir.join(attribute))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+    let read_id = |attribute: &str| {
+        read(attribute)
+            .and_then(|value| u16::from_str_radix(&value, 16).ok())
+            .unwrap_or_default()
+    };
+
+    DeviceInfo {
+        path: path.to_path_buf(),
+        name: read("name").unwrap_or_default(),
+        vendor: read_id("id/vendor"),
+        product: read_id("id/product"),
+        phys: read("phys").filter(|phys| !phys.is_empty()),
+        roles: Vec::new(),
     }
 }
 
-pub struct MouseDevices(Vec<Arc<Mutex<Device>>>);
+/// Looks up the udev `ID_SEAT` of an event node or its parent input device, defaulting to `seat0`.
+///
+/// `None` while udev hasn't written its data for a new device yet.
+fn seat(path: &Path) -> Option<String> {
+    // Without udev every device is on the default seat
+    if !Path::new(UDEV_DATA_DIR).exists() {
+        return Some(DEFAULT_SEAT.to_string());
+    }
+    let name = path.file_name()?;
+    let sysfs_dir = Path::new(SYSFS_INPUT_DIR).join(name);
+
+    // udev keeps the properties of device nodes under their major:minor, and of other devices
+    // under their subsystem and sysname
+    let node_id = fs::read_to_string(sysfs_dir.join("dev"))
+        .ok()
+        .map(|dev| format!("c{}", dev.trim()));
+    let parent_id = fs::canonicalize(sysfs_dir.join("device"))
+        .ok()
+        .and_then(|parent| parent.file_name().map(|name| name.to_os_string()))
+        .map(|name| format!("+input:{}", name.to_string_lossy()));
+
+    let data = [node_id, parent_id]
+        .into_iter()
+        .flatten()
+        .filter_map(|id| fs::read_to_string(Path::new(UDEV_DATA_DIR).join(id)).ok())
+        .collect::<Vec<_>>();
+    if data.is_empty() {
+        return None;
+    }
 
-impl MouseDevices {
-    pub fn try_new() -> anyhow::Result<Self> {
-        let devices = fs::read_dir("/dev/input")?
+    let seat = data.iter().find_map(|data| {
+        data.lines()
+            .find_map(|line| line.strip_prefix("E:ID_SEAT="))
+            .map(str::to_string)
+    });
+    Some(seat.unwrap_or_else(|| DEFAULT_SEAT.to_string()))
+}
+
+/// The event node a udev data entry like `c13:67` belongs to, if it belongs to one.
+pub fn udev_entry_node(entry: &OsStr) -> Option<PathBuf> {
+    let dev = entry.to_str()?.strip_prefix('c')?;
+    let sysfs_path = fs::read_link(Path::new(SYSFS_CHAR_DIR).join(dev)).ok()?;
+    let path = Path::new(INPUT_DIR).join(sysfs_path.file_name()?);
+
+    DeviceRegistry::is_event_node(&path).then_some(path)
+}
+
+pub static INPUT_DIR: &str = "/dev/input";
+static SYSFS_INPUT_DIR: &str = "/sys/class/input";
+static SYSFS_CHAR_DIR: &str = "/sys/dev/char";
+pub static UDEV_DATA_DIR: &str = "/run/udev/data";
+static DEFAULT_SEAT: &str = "seat0";
+
+/// Every input device the daemon reads from, each one opened exactly once.
+#[derive(Default)]
+pub struct DeviceRegistry(Vec<InputDevice>);
+
+impl DeviceRegistry {
+    pub fn is_event_node(path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+    }
+
+    pub fn event_nodes() -> anyhow::Result<Vec<PathBuf>> {
+        let paths = fs::read_dir(INPUT_DIR)?
             .filter_map(|entry| entry.map(|entry| entry.path()).ok())
-            .filter(|path| path.to_string_lossy().contains("event"))
-            .filter_map(|path| Device::open(&path).ok())
-            .filter(|device| {
-                device.supported_keys().is_some_and(|keys| {
-                    keys.contains(evdev::KeyCode::BTN_LEFT)
-                        || keys.contains(evdev::KeyCode::BTN_RIGHT)
-                        || keys.contains(evdev::KeyCode::BTN_MIDDLE)
-                })
-            })
-            .map(|device| {
-                if let Some(name) = device.name() {
-                    log::info!("Mouse registered {name}");
-                }
-                Arc::new(Mutex::new(device))
-            })
-            .collect::<Vec<_>>();
-
-        Ok(Self(devices))
-    }
-
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Arc<Mutex<Device>>> {
-        self.0.iter_mut()
+            .filter(|path| Self::is_event_node(path))
+            .collect();
+
+        Ok(paths)
+    }
+
+    /// Opens and classifies the device at `path`, unless it is already registered, filtered out or has no use.
+    pub fn open(
+        &self,
+        path: PathBuf,
+        own_devices: &OwnDevices,
+        config: &Config,
+    ) -> Option<(Arc<DeviceInfo>, RawDevice)> {
+        if own_devices.contains(&path) || self.0.iter().any(|device| device.info.path == path) {
+            return None;
+        }
+
+        let sysfs_info = sysfs_info(&path);
+        if !config.devices.permits(&sysfs_info) {
+            log::debug!(
+                "Device {} ({}) filtered out",
+                sysfs_info.name,
+                path.display()
+            );
+            return None;
+        }
+
+        if let Some(config_seat) = config.seat.as_ref() {
+            // Retried once udev wrote the device's data, see `hotplug::watch`
+            let Some(seat) = seat(&path) else {
+                log::debug!(
+                    "Device {} ({}) has no udev data yet",
+                    sysfs_info.name,
+                    path.display()
+                );
+                return None;
+            };
+            if seat != *config_seat {
+                log::debug!(
+                    "Device {} ({}) belongs to {seat}",
+                    sysfs_info.name,
+                    path.display()
+                );
+                return None;
+            }
+        }
+
+        let device = match RawDevice::open(&path) {
+            Ok(device) => device,
+            Err(e) => {
+                log::debug!("Failed to open {}: {e}", path.display());
+                return None;
+            }
+        };
+        let roles = classify(&device);
+        if roles.is_empty() {
+            return None;
+        }
+
+        if let Err(e) = set_nonblocking(&device) {
+            log::warn!("Failed to make {} non-blocking: {e}", path.display());
+            return None;
+        }
+
+        let info = DeviceInfo::new(path, &device, roles);
+        Some((Arc::new(info), device))
+    }
+
+    pub fn insert(&mut self, device: InputDevice) {
+        log::info!(
+            "Device registered {} as {:?}",
+            device.info.name,
+            device.info.roles
+        );
+        self.0.push(device);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &InputDevice> {
+        self.0.iter()
+    }
+
+    pub fn get_mut(&mut self, path: &Path) -> Option<&mut InputDevice> {
+        self.0.iter_mut().find(|device| device.info.path == path)
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<InputDevice> {
+        let index = self.0.iter().position(|device| device.info.path == path)?;
+        let device = self.0.remove(index);
+        log::info!("Device removed {}", device.info.name);
+
+        Some(device)
     }
 }
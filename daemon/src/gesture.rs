@@ -0,0 +1,129 @@
+use crate::Clicker;
+use calloop::{
+    LoopHandle, RegistrationToken,
+    timer::{TimeoutAction, Timer},
+};
+use common::{DoubleTap, LongPress};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct DoubleTapState {
+    last_tap: Option<Instant>,
+}
+
+impl DoubleTapState {
+    /// Records a press of the double-tap key, returns `true` when it completes a double tap.
+    pub fn tap(&mut self, double_tap: &DoubleTap, now: Instant) -> bool {
+        match self.last_tap.take() {
+            Some(last_tap)
+                if now.duration_since(last_tap) <= Duration::from_millis(double_tap.interval) =>
+            {
+                true
+            }
+            _ => {
+                self.last_tap = Some(now);
+                false
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last_tap = None;
+    }
+}
+
+#[derive(Default)]
+pub struct LongPressState {
+    registration_token: Option<RegistrationToken>,
+}
+
+impl LongPressState {
+    pub fn start(&mut self, long_press: &LongPress, handle: &LoopHandle<'_, Clicker>) {
+        self.cancel(handle);
+
+        let timer = Timer::from_duration(Duration::from_millis(long_press.duration));
+        match handle.insert_source(timer, |_, (), state| {
+            state.long_press.registration_token = None;
+
+            // Toggles like the other gestures, so a profile only activated by it can be turned off
+            if let Some(profile) = state.current_profile.as_ref() {
+                state.gesture_activated = !state.gesture_activated;
+                log::info!(
+                    "Profile '{}' {} by long press (cps={:?}, jitter={:?})",
+                    profile.name,
+                    if state.gesture_activated {
+                        "activated"
+                    } else {
+                        "deactivated"
+                    },
+                    profile.cps,
+                    profile.jitter
+                );
+            }
+
+            TimeoutAction::Drop
+        }) {
+            Ok(registration_token) => self.registration_token = Some(registration_token),
+            Err(e) => log::warn!("{e}"),
+        }
+    }
+
+    pub fn cancel(&mut self, handle: &LoopHandle<'_, Clicker>) {
+        if let Some(registration_token) = self.registration_token.take() {
+            handle.remove(registration_token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::KeyCode;
+
+    fn double_tap() -> DoubleTap {
+        DoubleTap {
+            key: KeyCode::KEY_F8,
+            interval: 300,
+        }
+    }
+
+    #[test]
+    fn double_tap_within_interval() {
+        let mut state = DoubleTapState::default();
+        let start = Instant::now();
+
+        assert!(!state.tap(&double_tap(), start));
+        assert!(state.tap(&double_tap(), start + Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn double_tap_outside_interval() {
+        let mut state = DoubleTapState::default();
+        let start = Instant::now();
+
+        assert!(!state.tap(&double_tap(), start));
+        assert!(!state.tap(&double_tap(), start + Duration::from_millis(301)));
+        // The late tap counts as the first tap of a new double tap
+        assert!(state.tap(&double_tap(), start + Duration::from_millis(400)));
+    }
+
+    #[test]
+    fn completed_double_tap_starts_over() {
+        let mut state = DoubleTapState::default();
+        let start = Instant::now();
+
+        assert!(!state.tap(&double_tap(), start));
+        assert!(state.tap(&double_tap(), start + Duration::from_millis(100)));
+        assert!(!state.tap(&double_tap(), start + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn reset_forgets_the_first_tap() {
+        let mut state = DoubleTapState::default();
+        let start = Instant::now();
+
+        assert!(!state.tap(&double_tap(), start));
+        state.reset();
+        assert!(!state.tap(&double_tap(), start + Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    Clicker,
+    device::{self, DeviceRegistry, INPUT_DIR, InputDevice, UDEV_DATA_DIR},
+    reader::Reader,
+};
+use calloop::{Interest, LoopHandle, Mode, PostAction, generic::Generic};
+use common::{device::DeviceInfo, ipc::IpcEvent};
+use inotify::{EventMask, Inotify, WatchMask};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+impl Clicker {
+    pub fn add_device(&mut self, path: PathBuf) {
+        let Some((info, device)) = self.devices.open(path, &self.own_devices, &self.config) else {
+            return;
+        };
+
+        let reader = Reader::new(
+            device,
+            Arc::clone(&info),
+            &self.config.grab,
+            &self.own_devices,
+        );
+        if let Some(key_state) = reader.key_state() {
+            self.handle_event(key_state);
+        }
+
+        let grabbed = reader.is_grabbed();
+        let source = Generic::new(reader, Interest::READ, Mode::Level);
+        let registration_token = match self.loop_handle.insert_source(source, |_, reader, state| {
+            // SAFETY: the device is only read from, never closed or replaced
+            let reader = unsafe { reader.get_mut() };
+            let was_grabbed = reader.is_grabbed();
+            match reader.read() {
+                Ok(events) => {
+                    // Grabbing waits for held keys to be released, so it can happen on any read
+                    if reader.is_grabbed() != was_grabbed
+                        && let Some(device) = state.devices.get_mut(&reader.info().path)
+                    {
+                        device.grabbed = reader.is_grabbed();
+                    }
+                    events
+                        .into_iter()
+                        .for_each(|event| state.handle_event(event));
+                    Ok(PostAction::Continue)
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    Ok(PostAction::Continue)
+                }
+                Err(e) => {
+                    // The fd stays readable after a fatal error, keeping the source would spin
+                    if e.raw_os_error() != Some(libc::ENODEV) {
+                        log::warn!("Failed to read events from {}: {e}", reader.info().name);
+                    }
+                    state.forget_device(&reader.info().path);
+                    Ok(PostAction::Remove)
+                }
+            }
+        }) {
+            Ok(registration_token) => registration_token,
+            Err(e) => {
+                log::warn!("Failed to watch device {}: {e}", info.name);
+                return;
+            }
+        };
+
+        let event = IpcEvent::DeviceAdded(DeviceInfo::clone(&info));
+        self.devices.insert(InputDevice {
+            info,
+            registration_token,
+            grabbed,
+        });
+        if let Err(e) = self.ipc.broadcast(&event) {
+            log::error!("Failed to broadcast event: {e}");
+        }
+    }
+
+    pub fn remove_device(&mut self, path: &Path) {
+        if let Some(device) = self.forget_device(path) {
+            self.loop_handle.remove(device.registration_token);
+        }
+    }
+
+    /// Drops a device from the registry, its event source is left for the caller to remove.
+    fn forget_device(&mut self, path: &Path) -> Option<InputDevice> {
+        let device = self.devices.remove(path)?;
+        self.release_device(&device.info);
+
+        let event = IpcEvent::DeviceRemoved(DeviceInfo::clone(&device.info));
+        if let Err(e) = self.ipc.broadcast(&event) {
+            log::error!("Failed to broadcast event: {e}");
+        }
+
+        Some(device)
+    }
+}
+
+/// Watches the input directory and keeps the device registry in sync with it.
+pub fn watch(handle: &LoopHandle<'static, Clicker>) -> anyhow::Result<()> {
+    let inotify = Inotify::init()?;
+    let input_watch = inotify.watches().add(
+        INPUT_DIR,
+        WatchMask::CREATE | WatchMask::ATTRIB | WatchMask::DELETE,
+    )?;
+    // The seat of a new device is only known once udev wrote its data, which follows the node
+    if let Err(e) = inotify
+        .watches()
+        .add(UDEV_DATA_DIR, WatchMask::MOVED_TO | WatchMask::CLOSE_WRITE)
+    {
+        log::debug!("Not watching {UDEV_DATA_DIR}: {e}");
+    }
+
+    let source = Generic::new(inotify, Interest::READ, Mode::Level);
+    handle
+        .insert_source(source, move |_, inotify, state| {
+            let mut buffer = [0; 4096];
+            // SAFETY: the inotify instance is only read from, never closed or replaced
+            let events = match unsafe { inotify.get_mut() }.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(PostAction::Continue);
+                }
+                Err(e) => return Err(e),
+            };
+
+            for event in events {
+                let Some(name) = event.name else {
+                    continue;
+                };
+
+                if event.wd != input_watch {
+                    if let Some(path) = device::udev_entry_node(name) {
+                        state.add_device(path);
+                    }
+                    continue;
+                }
+
+                let path = Path::new(INPUT_DIR).join(name);
+                if !DeviceRegistry::is_event_node(&path) {
+                    continue;
+                }
+
+                if event.mask.contains(EventMask::DELETE) {
+                    state.remove_device(&path);
+                } else {
+                    // Devices are usually created before udev grants us access, so retry on ATTRIB
+                    state.add_device(path);
+                }
+            }
+
+            Ok(PostAction::Continue)
+        })
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    Ok(())
+}
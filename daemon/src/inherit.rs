@@ -0,0 +1,213 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Fields a profile never takes from its parent.
+static OWN_FIELDS: [&str; 2] = ["name", "extends"];
+
+/// Fills in the fields of every profile from the profiles it `extends`, before deserializing.
+///
+/// Attribute sets are merged field by field, anything else set on the profile itself wins.
+/// The inherited field paths are recorded in the profile's `inherited` list.
+pub fn resolve(value: &mut Value) -> Vec<String> {
+    let Some(profiles) = value.get_mut("profiles").and_then(Value::as_array_mut) else {
+        return Vec::new();
+    };
+    let originals = profiles.clone();
+
+    let mut indices = HashMap::new();
+    for (index, profile) in originals.iter().enumerate() {
+        if let Some(name) = profile.get("name").and_then(Value::as_str) {
+            indices.entry(name).or_insert(index);
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for (index, profile) in profiles.iter_mut().enumerate() {
+        let name = profile
+            .get("name")
+            .and_then(Value::as_str)
+            .map(|name| format!("profile '{name}'"))
+            .unwrap_or_else(|| format!("profile #{index}"));
+        let Some(profile) = profile.as_object_mut() else {
+            continue;
+        };
+
+        if profile.contains_key("inherited") {
+            diagnostics.push(format!(
+                "{name}: inherited: is filled in from `extends` and can't be set"
+            ));
+            continue;
+        }
+
+        match ancestors(&originals, &indices, index) {
+            Ok(ancestors) => {
+                let mut inherited = Vec::new();
+                for ancestor in ancestors {
+                    if let Some(parent) = originals[ancestor].as_object() {
+                        merge(profile, parent, "", &mut inherited);
+                    }
+                }
+                if !inherited.is_empty() {
+                    let inherited = inherited.into_iter().map(Value::String).collect();
+                    profile.insert("inherited".to_string(), Value::Array(inherited));
+                }
+            }
+            Err(message) => diagnostics.push(format!("{name}: extends: {message}")),
+        }
+    }
+
+    diagnostics
+}
+
+/// Indices of the profiles `index` inherits from, nearest parent first.
+fn ancestors(
+    profiles: &[Value],
+    indices: &HashMap<&str, usize>,
+    index: usize,
+) -> Result<Vec<usize>, String> {
+    let mut chain = vec![index];
+
+    let mut current = index;
+    while let Some(extends) = profiles[current].get("extends") {
+        let Some(parent_name) = extends.as_str() else {
+            return Err("must be the name of a profile".to_string());
+        };
+        let Some(&parent) = indices.get(parent_name) else {
+            return Err(format!("profile '{parent_name}' doesn't exist"));
+        };
+
+        if chain.contains(&parent) {
+            chain.push(parent);
+            let names = chain
+                .iter()
+                .map(|index| profiles[*index]["name"].as_str().unwrap_or("?"))
+                .collect::<Vec<_>>();
+            return Err(format!("inheritance cycle {}", names.join(" -> ")));
+        }
+
+        chain.push(parent);
+        current = parent;
+    }
+
+    Ok(chain.split_off(1))
+}
+
+fn merge(
+    profile: &mut Map<String, Value>,
+    parent: &Map<String, Value>,
+    prefix: &str,
+    inherited: &mut Vec<String>,
+) {
+    for (key, parent_value) in parent.iter() {
+        if prefix.is_empty() && OWN_FIELDS.contains(&key.as_str()) {
+            continue;
+        }
+
+        let path = format!("{prefix}{key}");
+        match (profile.get_mut(key), parent_value) {
+            (None, _) => {
+                profile.insert(key.clone(), parent_value.clone());
+                inherited.push(path);
+            }
+            // Already taken from a nearer parent, only fill in what that one left out
+            (Some(Value::Object(own)), Value::Object(parent_object))
+                if inherited.contains(&path) =>
+            {
+                merge(own, parent_object, &format!("{path}."), &mut Vec::new());
+            }
+            (Some(Value::Object(own)), Value::Object(parent_object)) => {
+                merge(own, parent_object, &format!("{path}."), inherited);
+            }
+            (Some(_), _) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resolved(profiles: Value) -> (Vec<Value>, Vec<String>) {
+        let mut value = json!({ "profiles": profiles });
+        let diagnostics = resolve(&mut value);
+        (value["profiles"].as_array().unwrap().clone(), diagnostics)
+    }
+
+    #[test]
+    fn inherits_missing_fields_and_records_them() {
+        let (profiles, diagnostics) = resolved(json!([
+            { "name": "base", "toggle": false, "jitter": 2, "cps": { "target": 10, "std_dev": 1 } },
+            { "name": "fast", "extends": "base", "jitter": 0, "cps": { "target": 25 } },
+        ]));
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            profiles[1],
+            json!({
+                "name": "fast",
+                "extends": "base",
+                "toggle": false,
+                "jitter": 0,
+                "cps": { "target": 25, "std_dev": 1 },
+                "inherited": ["cps.std_dev", "toggle"],
+            })
+        );
+        assert!(profiles[0].get("inherited").is_none());
+    }
+
+    #[test]
+    fn nearer_parents_win() {
+        let (profiles, diagnostics) = resolved(json!([
+            { "name": "a", "toggle": false, "cps": { "target": 5, "std_dev": 3 } },
+            { "name": "b", "extends": "a", "cps": { "target": 10 } },
+            { "name": "c", "extends": "b" },
+        ]));
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(profiles[2]["cps"], json!({ "target": 10, "std_dev": 3 }));
+        assert_eq!(profiles[2]["toggle"], json!(false));
+        assert_eq!(profiles[2]["inherited"], json!(["cps", "toggle"]));
+    }
+
+    #[test]
+    fn reports_missing_parents() {
+        let (_, diagnostics) = resolved(json!([{ "name": "a", "extends": "missing" }]));
+        assert_eq!(
+            diagnostics,
+            vec!["profile 'a': extends: profile 'missing' doesn't exist"]
+        );
+
+        let (_, diagnostics) = resolved(json!([{ "name": "a", "extends": 1 }]));
+        assert_eq!(
+            diagnostics,
+            vec!["profile 'a': extends: must be the name of a profile"]
+        );
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let (_, diagnostics) = resolved(json!([
+            { "name": "a", "extends": "b" },
+            { "name": "b", "extends": "a" },
+            { "name": "c", "extends": "c" },
+        ]));
+        assert_eq!(
+            diagnostics,
+            vec![
+                "profile 'a': extends: inheritance cycle a -> b -> a",
+                "profile 'b': extends: inheritance cycle b -> a -> b",
+                "profile 'c': extends: inheritance cycle c -> c",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_inherited_set_by_the_user() {
+        let (_, diagnostics) = resolved(json!([{ "name": "a", "inherited": ["cps"] }]));
+        assert_eq!(
+            diagnostics,
+            vec!["profile 'a': inherited: is filled in from `extends` and can't be set"]
+        );
+    }
+}
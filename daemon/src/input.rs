@@ -0,0 +1,328 @@
+use crate::Clicker;
+use common::{Profile, device::DeviceInfo};
+use evdev::KeyCode;
+use std::{sync::Arc, time::Instant};
+
+#[derive(Debug)]
+pub enum KeyEvent {
+    Pressed {
+        device: Arc<DeviceInfo>,
+        key_code: KeyCode,
+    },
+    Released {
+        device: Arc<DeviceInfo>,
+        key_code: KeyCode,
+    },
+    Motion {
+        device: Arc<DeviceInfo>,
+        distance: u32,
+    },
+    /// Keys currently held on a device, queried from the kernel.
+    Resync {
+        device: Arc<DeviceInfo>,
+        keys: Vec<KeyCode>,
+    },
+}
+
+fn is_trigger_key(profile: &Profile, key_code: KeyCode) -> bool {
+    key_code == profile.trigger()
+        || profile
+            .activation_keys
+            .as_ref()
+            .is_some_and(|keys| keys.contains(&key_code))
+        || profile
+            .activation_sequence
+            .as_ref()
+            .is_some_and(|sequence| sequence.keys.contains(&key_code))
+        || profile
+            .double_tap
+            .as_ref()
+            .is_some_and(|double_tap| double_tap.key == key_code)
+        || profile
+            .long_press
+            .as_ref()
+            .is_some_and(|long_press| long_press.key == key_code)
+}
+
+pub fn has_gestures(profile: &Profile) -> bool {
+    profile.activation_sequence.is_some()
+        || profile.double_tap.is_some()
+        || profile.long_press.is_some()
+}
+
+/// Whether both profiles define the same gestures, so gesture progress carries over between them.
+pub fn same_gestures(profile: &Profile, other: &Profile) -> bool {
+    profile.activation_sequence == other.activation_sequence
+        && profile.double_tap == other.double_tap
+        && profile.long_press == other.long_press
+}
+
+fn is_activation_device(profile: &Profile, device: &DeviceInfo) -> bool {
+    profile.activation_devices.is_empty()
+        || profile
+            .activation_devices
+            .iter()
+            .any(|device_match| device_match.matches(device))
+}
+
+fn log_profile_details(profile: &Profile, activated: bool) {
+    log::info!(
+        "Profile '{}' {} (toggle={}, keys={:?}, cps={:?}), jitter={:?}",
+        profile.name,
+        if activated {
+            "activated"
+        } else {
+            "deactivated"
+        },
+        profile.toggle,
+        profile.activation_keys.as_deref().unwrap_or_default(),
+        profile.cps,
+        profile.jitter
+    );
+}
+
+impl Clicker {
+    pub fn handle_event(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Pressed { device, key_code } => {
+                if !self.own_devices.contains(&device.path) {
+                    self.key_pressed(&device, key_code);
+                }
+            }
+            KeyEvent::Released { device, key_code } => {
+                if !self.own_devices.contains(&device.path) {
+                    self.key_released(&device, key_code);
+                }
+            }
+            KeyEvent::Motion { device, distance } => {
+                if !self.own_devices.contains(&device.path) {
+                    self.motion(distance);
+                }
+            }
+            KeyEvent::Resync { device, keys } => {
+                if !self.own_devices.contains(&device.path) {
+                    self.resync(&device, keys);
+                }
+            }
+        }
+    }
+
+    /// Whether the current profile reacts to events from `device`.
+    pub fn uses_device(&self, device: &DeviceInfo) -> bool {
+        self.current_profile
+            .as_ref()
+            .is_some_and(|profile| is_activation_device(profile, device))
+    }
+
+    /// An empty `activation_keys` list keeps a profile active, without one only its gestures activate it.
+    pub fn is_activated(&self, profile: &Profile) -> bool {
+        let all_keys_pressed = profile.activation_keys.as_ref().is_some_and(|keys| {
+            keys.iter()
+                .all(|profile_key| self.pressed_keys.contains(profile_key))
+        });
+
+        all_keys_pressed || (has_gestures(profile) && self.gesture_activated)
+    }
+
+    pub fn reset_gestures(&mut self) {
+        self.sequence.reset();
+        self.double_tap.reset();
+        self.long_press.cancel(&self.loop_handle);
+        self.gesture_activated = false;
+    }
+
+    pub fn stop_clicking(&mut self) -> bool {
+        match self.registration_token.take() {
+            Some(registration_token) => {
+                self.loop_handle.remove(registration_token);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn key_pressed(&mut self, device: &DeviceInfo, key_code: KeyCode) {
+        let held_keys = self.held_keys.entry(device.path.clone()).or_default();
+        if !held_keys.contains(&key_code) {
+            held_keys.push(key_code);
+        }
+
+        let Some(current_profile) = self.current_profile.clone() else {
+            return;
+        };
+
+        if self.registration_token.is_some()
+            && !is_trigger_key(&current_profile, key_code)
+            && let Some(threshold) = current_profile
+                .stop_on_user_input
+                .as_ref()
+                .and_then(|stop_on_user_input| stop_on_user_input.keys)
+        {
+            self.user_key_presses += 1;
+            if self.user_key_presses >= threshold && self.stop_clicking() {
+                log::info!(
+                    "Autoclicker stopped using profile '{}' after {} key presses from the user",
+                    current_profile.name,
+                    self.user_key_presses
+                );
+            }
+        }
+
+        let was_activated = self.is_activated(&current_profile);
+
+        if is_activation_device(&current_profile, device) {
+            if let Some(sequence) = current_profile.activation_sequence.as_ref()
+                && self.sequence.advance(sequence, key_code, Instant::now())
+            {
+                self.gesture_activated = !self.gesture_activated;
+            }
+
+            if let Some(double_tap) = current_profile.double_tap.as_ref()
+                && double_tap.key == key_code
+                && self.double_tap.tap(double_tap, Instant::now())
+            {
+                self.gesture_activated = !self.gesture_activated;
+            }
+
+            if let Some(long_press) = current_profile.long_press.as_ref()
+                && long_press.key == key_code
+            {
+                self.long_press.start(long_press, &self.loop_handle);
+            }
+
+            if self.pressed_keys.contains(&key_code) {
+                self.pressed_keys
+                    .retain(|pressed_key| pressed_key != &key_code);
+            } else if key_code != current_profile.trigger() {
+                self.pressed_keys.push(key_code);
+            }
+        }
+
+        if self.is_activated(&current_profile) {
+            if current_profile.trigger() == key_code {
+                // Already clicking, the trigger is held on another device or was resynced
+                if self.registration_token.is_some() {
+                    return;
+                }
+                log::info!(
+                    "Autoclicker started using profile '{}' with trigger {:?} and repeat key {:?} (CPS={:?}, jitter={:?})",
+                    current_profile.name,
+                    current_profile.trigger(),
+                    current_profile.repeat_key,
+                    current_profile.cps,
+                    current_profile.jitter
+                );
+                self.user_motion = 0;
+                self.user_key_presses = 0;
+                self.registration_token = self.virtual_pointer.schedule_clicks(&self.loop_handle);
+            } else {
+                log_profile_details(&current_profile, true);
+            }
+        } else if was_activated {
+            log_profile_details(&current_profile, false);
+        }
+    }
+
+    fn key_released(&mut self, device: &DeviceInfo, key_code: KeyCode) {
+        if let Some(held_keys) = self.held_keys.get_mut(&device.path) {
+            held_keys.retain(|held_key| held_key != &key_code);
+        }
+
+        let Some(current_profile) = self.current_profile.clone() else {
+            return;
+        };
+
+        if let Some(long_press) = current_profile.long_press.as_ref()
+            && long_press.key == key_code
+        {
+            self.long_press.cancel(&self.loop_handle);
+        }
+
+        if key_code == current_profile.trigger() && self.stop_clicking() {
+            log::info!(
+                "Autoclicker stopped using profile '{}' with trigger {:?} and repeat key {:?} (CPS={:?}, jitter={:?})",
+                current_profile.name,
+                current_profile.trigger(),
+                current_profile.repeat_key,
+                current_profile.cps,
+                current_profile.jitter
+            );
+        }
+    }
+
+    fn motion(&mut self, distance: u32) {
+        if self.registration_token.is_none() {
+            return;
+        }
+
+        let Some(current_profile) = self.current_profile.as_ref() else {
+            return;
+        };
+        let Some(threshold) = current_profile
+            .stop_on_user_input
+            .as_ref()
+            .and_then(|stop_on_user_input| stop_on_user_input.motion)
+        else {
+            return;
+        };
+        let name = current_profile.name.clone();
+
+        self.user_motion = self.user_motion.saturating_add(distance);
+        if self.user_motion >= threshold && self.stop_clicking() {
+            log::info!("Autoclicker stopped using profile '{name}' after the user moved the mouse");
+        }
+    }
+
+    /// Releases every key still held on a device that went away, stopping clicks it triggered.
+    pub fn release_device(&mut self, device: &DeviceInfo) {
+        let Some(held_keys) = self.held_keys.remove(&device.path) else {
+            return;
+        };
+
+        for key_code in held_keys.iter() {
+            log::debug!("Released {key_code:?} for removed device {}", device.name);
+            self.key_released(device, *key_code);
+        }
+        self.pressed_keys
+            .retain(|pressed_key| !held_keys.contains(pressed_key));
+    }
+
+    /// Replays the difference between the keys we believe are held and the kernel's view of them.
+    fn resync(&mut self, device: &DeviceInfo, keys: Vec<KeyCode>) {
+        let previous = self
+            .held_keys
+            .get(&device.path)
+            .cloned()
+            .unwrap_or_default();
+
+        // A key another device still holds only changes this device's held keys, replaying it
+        // would toggle the activation chord or stop clicks the other device started
+        for key_code in previous.iter().filter(|key_code| !keys.contains(key_code)) {
+            if self.held_elsewhere(device, *key_code) {
+                if let Some(held_keys) = self.held_keys.get_mut(&device.path) {
+                    held_keys.retain(|held_key| held_key != key_code);
+                }
+            } else {
+                log::debug!("Resync released {key_code:?} for device {}", device.name);
+                self.key_released(device, *key_code);
+            }
+        }
+        for key_code in keys.iter().filter(|key_code| !previous.contains(key_code)) {
+            if self.held_elsewhere(device, *key_code) {
+                self.held_keys
+                    .entry(device.path.clone())
+                    .or_default()
+                    .push(*key_code);
+            } else {
+                log::debug!("Resync pressed {key_code:?} for device {}", device.name);
+                self.key_pressed(device, *key_code);
+            }
+        }
+    }
+
+    fn held_elsewhere(&self, device: &DeviceInfo, key_code: KeyCode) -> bool {
+        self.held_keys
+            .iter()
+            .any(|(path, held_keys)| *path != device.path && held_keys.contains(&key_code))
+    }
+}
@@ -1,25 +1,59 @@
 mod config;
 mod device;
+mod gesture;
+mod hotplug;
+mod inherit;
+mod input;
+mod overlay;
+mod passthrough;
+mod profiles;
+mod reader;
+mod reload;
+mod sequence;
+mod validate;
 mod virtual_pointer;
 
-use async_std::task;
 use calloop::{EventLoop, LoopHandle, RegistrationToken, generic::Generic};
 use clap::Parser;
 use common::{
     Profile,
+    device::{DeviceEntry, DeviceInfo},
     ipc::{self, IpcRequest, IpcResponse, Server},
 };
+use device::{DeviceRegistry, OwnDevices};
 use env_logger::Builder;
-use evdev::{EventType, KeyCode};
+use evdev::KeyCode;
+use gesture::{DoubleTapState, LongPressState};
 use log::LevelFilter;
-use std::{io::Write, os::fd::AsRawFd, path::PathBuf, sync::Arc};
+use overlay::Overlay;
+use sequence::SequenceState;
+use std::{
+    collections::HashMap,
+    io::Write,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
 use virtual_pointer::VirtualPointer;
 
 struct Clicker {
     ipc: ipc::Ipc<Server>,
     config: config::Config,
+    config_path: PathBuf,
+    /// Profile changes made over IPC that aren't persisted.
+    overlay: Overlay,
+    /// Set with `--log-level`, which takes precedence over the config's log level.
+    cli_log_level: Option<LevelFilter>,
     current_profile: Option<Profile>,
+    own_devices: OwnDevices,
+    devices: DeviceRegistry,
+    held_keys: HashMap<PathBuf, Vec<KeyCode>>,
     pressed_keys: Vec<KeyCode>,
+    sequence: SequenceState,
+    double_tap: DoubleTapState,
+    long_press: LongPressState,
+    gesture_activated: bool,
+    user_motion: u32,
+    user_key_presses: u32,
     registration_token: Option<RegistrationToken>,
     virtual_pointer: VirtualPointer,
     loop_handle: LoopHandle<'static, Self>,
@@ -29,25 +63,47 @@ impl Clicker {
     fn new(
         ipc: ipc::Ipc<Server>,
         config: config::Config,
+        config_path: PathBuf,
+        cli_log_level: Option<LevelFilter>,
+        default_profile: Option<&str>,
         loop_handle: LoopHandle<'static, Self>,
-    ) -> Self {
-        let virtual_pointer = VirtualPointer::try_new(&config).unwrap();
-
-        let current_profile = config
-            .profiles
-            .iter()
-            .find(|profile| profile.name == "default")
-            .cloned();
+    ) -> anyhow::Result<Self> {
+        let own_devices = OwnDevices::default();
+        let virtual_pointer = VirtualPointer::try_new(&config, &own_devices)?;
+
+        let current_profile = default_profile.and_then(|name| {
+            let profile = config
+                .profiles
+                .iter()
+                .find(|profile| profile.name == name)
+                .cloned();
+            if profile.is_none() {
+                log::warn!("Default profile '{name}' doesn't exist, starting without a profile");
+            }
+            profile
+        });
 
-        Self {
+        Ok(Self {
             ipc,
             config,
+            config_path,
+            overlay: Overlay::default(),
+            cli_log_level,
             virtual_pointer,
+            own_devices,
+            devices: DeviceRegistry::default(),
+            held_keys: HashMap::new(),
             pressed_keys: Vec::new(),
+            sequence: SequenceState::default(),
+            double_tap: DoubleTapState::default(),
+            long_press: LongPressState::default(),
+            gesture_activated: false,
+            user_motion: 0,
+            user_key_presses: 0,
             registration_token: None,
             current_profile,
             loop_handle,
-        }
+        })
     }
 }
 
@@ -59,234 +115,131 @@ struct Cli {
 
     #[arg(short, long, value_name = "FILE", help = "Path to the config file")]
     config: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", help = "Path to the IPC socket")]
+    socket: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "NAME",
+        help = "Profile to select at startup"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "profile",
+        help = "Start without a profile selected"
+    )]
+    no_profile: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        help = "Validate the config file and exit"
+    )]
+    check_config: Option<Option<PathBuf>>,
+
+    #[arg(
+        long,
+        requires = "check_config",
+        help = "Print the problems found by --check-config as JSON"
+    )]
+    json: bool,
 }
 
-#[derive(Debug)]
-enum KeyEvent {
-    Pressed {
-        device_name: Arc<str>,
-        key_code: KeyCode,
-    },
-    Released {
-        device_name: Arc<str>,
-        key_code: KeyCode,
-    },
+fn check_config(config_path: &Path, json: bool) -> anyhow::Result<()> {
+    let result = config::Config::check(config_path, &Overlay::default());
+
+    if json {
+        let diagnostics = result.err().unwrap_or_default();
+        println!("{}", serde_json::to_string(&diagnostics)?);
+        if !diagnostics.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    match result {
+        Ok(_) => {
+            println!("{} is valid", config_path.display());
+            Ok(())
+        }
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{diagnostic}");
+            }
+            Err(anyhow::anyhow!(
+                "{} problem(s) found in {}",
+                diagnostics.len(),
+                config_path.display()
+            ))
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    // The global max level does the filtering, so the config can still lower or raise it
     Builder::new()
-        .filter(Some("daemon"), cli.log_level.unwrap_or(LevelFilter::Info))
+        .filter(Some("daemon"), LevelFilter::Trace)
         .init();
+    log::set_max_level(cli.log_level.unwrap_or(LevelFilter::Info));
+
+    if let Some(path) = cli.check_config {
+        let config_path = match path.or(cli.config) {
+            Some(path) => path,
+            None => config::Config::path()?,
+        };
+        return check_config(&config_path, cli.json);
+    }
+
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => config::Config::path()?,
+    };
+    let config = config::Config::load(&config_path, &Overlay::default())?;
 
-    let config = config::Config::load(cli.config).unwrap();
+    if cli.log_level.is_none()
+        && let Some(log_level) = config.settings.log_level
+    {
+        log::set_max_level(log_level);
+    }
+
+    let default_profile = if cli.no_profile {
+        None
+    } else {
+        cli.profile.or(config.settings.default_profile.clone())
+    };
+    let socket = cli
+        .socket
+        .or(config.settings.socket.clone())
+        .unwrap_or_else(|| ipc::DEFAULT_SOCKET.clone());
 
     let mut event_loop = EventLoop::try_new()?;
 
-    let ipc = ipc::Ipc::server()?;
-
-    let mut clicker = Clicker::new(ipc, config, event_loop.handle());
-
-    let (executor, scheduler) = calloop::futures::executor()?;
-    let (event_sender, event_receiver) = calloop::channel::channel();
-
-    scheduler.schedule(async move {
-        device::MouseDevices::try_new()
-            .unwrap()
-            .iter_mut()
-            .for_each(|device| {
-                let device = Arc::clone(&device);
-                let event_sender = event_sender.clone();
-                task::spawn(async move {
-                    let mut device = device.lock().unwrap();
-                    let device_name: Arc<str> = device.name().unwrap_or_default().into();
-                    loop {
-                        if let Ok(events) = device.fetch_events() {
-                            for ev in events {
-                                let EventType::KEY = ev.event_type() else {
-                                    continue;
-                                };
-
-                                let code = KeyCode::new(ev.code());
-                                let key_event = match ev.value() {
-                                    0 => {
-                                        log::debug!(
-                                            "Mouse released {code:?} for device {}",
-                                            device_name
-                                        );
-                                        KeyEvent::Released {
-                                            device_name: Arc::clone(&device_name),
-                                            key_code: code,
-                                        }
-                                    }
-                                    1 => {
-                                        log::debug!(
-                                            "Mouse pressed {code:?} for device {}",
-                                            device_name
-                                        );
-                                        KeyEvent::Pressed {
-                                            device_name: Arc::clone(&device_name),
-                                            key_code: code,
-                                        }
-                                    }
-                                    _ => continue,
-                                };
-
-                                if let Err(e) = event_sender.send(key_event) {
-                                    log::warn!("{e}");
-                                }
-                            }
-                        }
-                    }
-                });
-            });
-
-        device::KeyboardDevices::try_new()
-            .unwrap()
-            .iter_mut()
-            .for_each(|device| {
-                let device = Arc::clone(&device);
-                let event_sender = event_sender.clone();
-                task::spawn(async move {
-                    let mut device = device.lock().unwrap();
-                    let device_name: Arc<str> = device.name().unwrap_or_default().into();
-                    loop {
-                        if let Ok(events) = device.fetch_events() {
-                            for ev in events {
-                                let EventType::KEY = ev.event_type() else {
-                                    continue;
-                                };
-
-                                let key_code = KeyCode::new(ev.code());
-                                let key_event = match ev.value() {
-                                    0 => {
-                                        log::debug!(
-                                            "Key released {key_code:?} for device {}",
-                                            device_name
-                                        );
-                                        KeyEvent::Released {
-                                            device_name: Arc::clone(&device_name),
-                                            key_code,
-                                        }
-                                    }
-                                    1 => {
-                                        log::debug!(
-                                            "Key pressed {key_code:?} for device {}",
-                                            device_name
-                                        );
-                                        KeyEvent::Pressed {
-                                            device_name: Arc::clone(&device_name),
-                                            key_code,
-                                        }
-                                    }
-                                    _ => continue,
-                                };
-
-                                if let Err(e) = event_sender.send(key_event) {
-                                    log::warn!("{e}");
-                                }
-                            }
-                        }
-                    }
-                });
-            });
-    })?;
-    event_loop
-        .handle()
-        .insert_source(executor, |_: (), _, _| ())
-        .map_err(|e| anyhow::anyhow!("{e}"))?;
-
-    event_loop
-        .handle()
-        .insert_source(
-            event_receiver,
-            |event: calloop::channel::Event<KeyEvent>, _, state| {
-                let calloop::channel::Event::Msg(event) = event else {
-                    return;
-                };
-                let Some(current_profile) = state.current_profile.as_ref() else {
-                    return;
-                };
-
-                let log_profile_details = |activated: bool| {
-                    log::info!(
-                        "Profile '{}' {} (toggle={}, keys={:?}, cps={:?}), jitter={:?}",
-                        current_profile.name,
-                        if activated {
-                            "activated"
-                        } else {
-                            "deactivated"
-                        },
-                        current_profile.toggle,
-                        current_profile.activation_keys,
-                        current_profile.cps,
-                        current_profile.jitter
-                    );
-                };
-
-                match event {
-                    KeyEvent::Pressed{ device_name, key_code} => {
-                        if &*device_name == "clicker-rs" {
-                            return;
-                        }
-
-                        if state.pressed_keys.contains(&key_code) {
-                        let all_keys_pressed = current_profile
-                            .activation_keys
-                            .iter()
-                            .all(|profile_key| state.pressed_keys.contains(profile_key));
-                            if all_keys_pressed {
-                                log_profile_details(false);
-                            }
-                            state.pressed_keys.retain(|pressed_key| pressed_key != &key_code);
-                        } else if key_code != current_profile.repeat_key {
-                            state.pressed_keys.push(key_code);
-                        }
-
-                        let all_keys_pressed = current_profile
-                            .activation_keys
-                            .iter()
-                            .all(|profile_key| state.pressed_keys.contains(profile_key));
-
-                        if all_keys_pressed {
-                            if current_profile.repeat_key == key_code {
-                                log::info!(
-                                    "Autoclicker started using profile '{}' with repeat key {:?} (CPS={:?}, jitter={:?})",
-                                    current_profile.name,
-                                    current_profile.repeat_key,
-                                    current_profile.cps,
-                                    current_profile.jitter
-                                );
-                                state.registration_token =
-                                    state.virtual_pointer.schedule_clicks(&state.loop_handle);
-                            } else {
-                                log_profile_details(true);
-                            }
-                        }
-                    }
-                    KeyEvent::Released{ device_name, key_code} => {
-                        if &*device_name == "clicker-rs" {
-                            return;
-                        }
-
-                        if key_code == current_profile.repeat_key {
-                            if let Some(registration_token) = state.registration_token.take() {
-                                log::info!(
-                                    "Autoclicker stopped using profile '{}' with repeat key {:?} (CPS={:?}, jitter={:?})",
-                                    current_profile.name,
-                                    current_profile.repeat_key,
-                                    current_profile.cps,
-                                    current_profile.jitter
-                                );
-                                state.loop_handle.remove(registration_token);
-                            }
-                        }
-                    }
-                }
-            },
-        )
-        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    let ipc = ipc::Ipc::server(&socket)?;
+    if let Err(e) = ipc::record_socket(&socket) {
+        log::warn!("Failed to record the socket path, clients need --socket to find it: {e}");
+    }
+
+    reload::watch(&event_loop.handle(), &config_path)?;
+    let mut clicker = Clicker::new(
+        ipc,
+        config,
+        config_path,
+        cli.log_level,
+        default_profile.as_deref(),
+        event_loop.handle(),
+    )?;
+
+    hotplug::watch(&event_loop.handle())?;
+    for path in DeviceRegistry::event_nodes()? {
+        clicker.add_device(path);
+    }
 
     let source = unsafe {
         Generic::new(
@@ -304,6 +257,73 @@ fn main() -> anyhow::Result<()> {
 
         let req = state.ipc.handle_stream_data(fd);
         let res = match req {
+            Ok(IpcRequest::Subscribe) => {
+                log::info!("IPC: Subscribe requested");
+                match state.ipc.subscribe(fd) {
+                    Ok(()) => IpcResponse::Ok,
+                    Err(e) => IpcResponse::Error(format!("Failed to subscribe: {e}")),
+                }
+            }
+            Ok(IpcRequest::ListDevices) => {
+                log::info!("IPC: ListDevices requested");
+                let devices = state
+                    .devices
+                    .iter()
+                    .map(|device| DeviceEntry {
+                        info: DeviceInfo::clone(&device.info),
+                        grabbed: device.grabbed,
+                        used: state.uses_device(&device.info),
+                    })
+                    .collect();
+                IpcResponse::Devices(devices)
+            }
+            Ok(IpcRequest::GetVirtualDevice) => {
+                log::info!("IPC: GetVirtualDevice requested");
+                IpcResponse::VirtualDevice(state.virtual_pointer.info())
+            }
+            Ok(IpcRequest::Reload) => {
+                log::info!("IPC: Reload requested");
+                match state.reload_config() {
+                    Ok(()) => IpcResponse::Ok,
+                    Err(e) => IpcResponse::Error(format!("Failed to reload config: {e}")),
+                }
+            }
+            Ok(IpcRequest::Validate) => {
+                log::info!("IPC: Validate requested");
+                match config::Config::check(&state.config_path, &Overlay::default()) {
+                    Ok(_) => IpcResponse::Diagnostics(Vec::new()),
+                    Err(diagnostics) => IpcResponse::Diagnostics(diagnostics),
+                }
+            }
+            Ok(IpcRequest::CreateProfile {
+                name,
+                fields,
+                persist,
+            }) => {
+                log::info!("IPC: CreateProfile requested for '{}'", name);
+                match state.create_profile(&name, fields, persist) {
+                    Ok(()) => IpcResponse::Ok,
+                    Err(e) => IpcResponse::Error(e.to_string()),
+                }
+            }
+            Ok(IpcRequest::UpdateProfile {
+                name,
+                fields,
+                persist,
+            }) => {
+                log::info!("IPC: UpdateProfile requested for '{}'", name);
+                match state.update_profile(&name, fields, persist) {
+                    Ok(()) => IpcResponse::Ok,
+                    Err(e) => IpcResponse::Error(e.to_string()),
+                }
+            }
+            Ok(IpcRequest::DeleteProfile { name, persist }) => {
+                log::info!("IPC: DeleteProfile requested for '{}'", name);
+                match state.delete_profile(&name, persist) {
+                    Ok(()) => IpcResponse::Ok,
+                    Err(e) => IpcResponse::Error(e.to_string()),
+                }
+            }
             Ok(IpcRequest::GetAllProfiles) => {
                 log::info!("IPC: GetAllProfiles requested");
                 IpcResponse::AllProfiles(state.config.profiles.clone())
@@ -345,8 +365,13 @@ fn main() -> anyhow::Result<()> {
                     .find(|profile| profile.name == name)
                 {
                     Some(profile) => {
-                        state.current_profile = Some(profile.clone());
+                        let profile = profile.clone();
                         log::info!("IPC: Switched to profile '{}'", profile.name);
+                        state
+                            .virtual_pointer
+                            .ensure_keys(std::slice::from_ref(&profile), &state.own_devices);
+                        state.current_profile = Some(profile);
+                        state.reset_gestures();
                         IpcResponse::Ok
                     }
                     None => {
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Profile changes merged over the config file, a `None` entry deletes the profile.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Overlay {
+    #[serde(default)]
+    profiles: BTreeMap<String, Option<Value>>,
+}
+
+impl Overlay {
+    /// Reads a persisted overlay, a missing file is an empty overlay.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write and rename, so a crash never leaves a truncated overlay behind
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: &str, profile: Option<Value>) {
+        self.profiles.insert(name.to_string(), profile);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.profiles.remove(name);
+    }
+
+    /// Replaces, adds or deletes the overlaid profiles in an evaluated config.
+    pub fn apply(&self, value: &mut Value) {
+        if self.profiles.is_empty() {
+            return;
+        }
+
+        let Some(config) = value.as_object_mut() else {
+            return;
+        };
+        let Some(profiles) = config
+            .entry("profiles")
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+        else {
+            return;
+        };
+
+        for (name, profile) in self.profiles.iter() {
+            let index = profiles
+                .iter()
+                .position(|existing| existing.get("name").and_then(Value::as_str) == Some(name));
+            match (index, profile) {
+                (Some(index), Some(profile)) => profiles[index] = profile.clone(),
+                (None, Some(profile)) => profiles.push(profile.clone()),
+                (Some(index), None) => {
+                    profiles.remove(index);
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// Merges changed fields into a profile, attribute sets field by field and `null` unsetting a field.
+pub fn merge_fields(profile: &mut Map<String, Value>, fields: Map<String, Value>) {
+    for (key, value) in fields {
+        match (profile.get_mut(&key), value) {
+            (_, Value::Null) => {
+                profile.remove(&key);
+            }
+            (Some(Value::Object(own)), Value::Object(changes)) => merge_fields(own, changes),
+            (_, value) => {
+                profile.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn merge_fields_unsets_null_fields() {
+        let mut profile = fields(json!({ "name": "a", "jitter": 2, "cps": { "target": 10 } }));
+        merge_fields(
+            &mut profile,
+            fields(json!({ "jitter": null, "toggle": null })),
+        );
+        assert_eq!(
+            Value::Object(profile),
+            json!({ "name": "a", "cps": { "target": 10 } })
+        );
+    }
+
+    #[test]
+    fn merge_fields_merges_nested_fields() {
+        let mut profile = fields(json!({ "cps": { "target": 10, "std_dev": 2 }, "toggle": true }));
+        merge_fields(
+            &mut profile,
+            fields(json!({ "cps": { "target": 20, "std_dev": null }, "toggle": false })),
+        );
+        assert_eq!(
+            Value::Object(profile),
+            json!({ "cps": { "target": 20 }, "toggle": false })
+        );
+    }
+
+    #[test]
+    fn apply_replaces_adds_and_deletes_profiles() {
+        let mut value = json!({
+            "profiles": [
+                { "name": "a", "jitter": 1 },
+                { "name": "b" },
+            ],
+        });
+
+        let mut overlay = Overlay::default();
+        overlay.set("a", Some(json!({ "name": "a", "jitter": 5 })));
+        overlay.set("b", None);
+        overlay.set("c", Some(json!({ "name": "c" })));
+        overlay.set("missing", None);
+        overlay.apply(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "profiles": [
+                    { "name": "a", "jitter": 5 },
+                    { "name": "c" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn apply_adds_profiles_to_configs_without_any() {
+        let mut value = json!({});
+        let mut overlay = Overlay::default();
+        overlay.set("a", Some(json!({ "name": "a" })));
+        overlay.apply(&mut value);
+
+        assert_eq!(value, json!({ "profiles": [{ "name": "a" }] }));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::device::OwnDevices;
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    EventType, InputEvent, KeyCode, SynchronizationCode, UinputAbsSetup, raw_stream::RawDevice,
+};
+use std::{io, path::PathBuf};
+
+/// Re-emits the events of a grabbed device, except for the swallowed trigger keys.
+pub struct Passthrough {
+    virtual_device: VirtualDevice,
+    swallowed_keys: Vec<KeyCode>,
+    pending: Vec<InputEvent>,
+    /// Keys the passthrough device currently reports as held.
+    held_keys: Vec<KeyCode>,
+    own_devices: OwnDevices,
+    dev_nodes: Vec<PathBuf>,
+    grabbed: bool,
+}
+
+impl Passthrough {
+    /// Creates the passthrough device, the grabbed device is only grabbed by [`Passthrough::try_grab`].
+    pub fn try_new(
+        device: &mut RawDevice,
+        swallowed_keys: &[KeyCode],
+        own_devices: &OwnDevices,
+    ) -> anyhow::Result<Self> {
+        let mut builder = VirtualDevice::builder()?
+            .name("clicker-rs passthrough")
+            .input_id(device.input_id())
+            .with_properties(device.properties())?;
+
+        if let Some(keys) = device.supported_keys() {
+            builder = builder.with_keys(keys)?;
+        }
+        if let Some(relative_axes) = device.supported_relative_axes() {
+            builder = builder.with_relative_axes(relative_axes)?;
+        }
+        if let Some(misc) = device.misc_properties() {
+            builder = builder.with_msc(misc)?;
+        }
+        if device.supported_absolute_axes().is_some() {
+            for (axis, absinfo) in device.get_absinfo()? {
+                builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, absinfo))?;
+            }
+        }
+
+        let mut virtual_device = builder.build()?;
+        let dev_nodes = own_devices.register(&mut virtual_device)?;
+
+        Ok(Self {
+            virtual_device,
+            swallowed_keys: swallowed_keys.to_vec(),
+            pending: Vec::new(),
+            held_keys: Vec::new(),
+            own_devices: own_devices.clone(),
+            dev_nodes,
+            grabbed: false,
+        })
+    }
+
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    /// Grabs the device unless keys are held on it, the compositor would never see them released.
+    pub fn try_grab(&mut self, device: &mut RawDevice) -> io::Result<bool> {
+        if device.get_key_state()?.iter().next().is_some() {
+            return Ok(false);
+        }
+
+        device.grab()?;
+        // A key pressed between the check and the grab already reached the compositor
+        if device.get_key_state()?.iter().next().is_some() {
+            device.ungrab()?;
+            return Ok(false);
+        }
+
+        self.grabbed = true;
+        Ok(true)
+    }
+
+    /// Forwards an event once the device is grabbed, before that the compositor still receives it.
+    pub fn forward(&mut self, event: InputEvent) {
+        if !self.grabbed {
+            return;
+        }
+
+        match event.event_type() {
+            EventType::SYNCHRONIZATION if event.code() == SynchronizationCode::SYN_DROPPED.0 => {
+                self.pending.clear();
+            }
+            EventType::SYNCHRONIZATION if event.code() == SynchronizationCode::SYN_REPORT.0 => {
+                if self.pending.is_empty() {
+                    return;
+                }
+
+                let pending = std::mem::take(&mut self.pending);
+                self.emit(&pending);
+            }
+            EventType::SYNCHRONIZATION => {}
+            EventType::KEY if self.swallowed_keys.contains(&KeyCode::new(event.code())) => {}
+            _ => self.pending.push(event),
+        }
+    }
+
+    /// Releases and presses keys until the passthrough device matches the grabbed device's `keys`.
+    pub fn resync(&mut self, keys: &[KeyCode]) {
+        if !self.grabbed {
+            return;
+        }
+        self.pending.clear();
+
+        let released = self
+            .held_keys
+            .iter()
+            .filter(|key_code| !keys.contains(key_code))
+            .map(|key_code| InputEvent::new(EventType::KEY.0, key_code.code(), 0));
+        let pressed = keys
+            .iter()
+            .filter(|key_code| {
+                !self.held_keys.contains(key_code) && !self.swallowed_keys.contains(key_code)
+            })
+            .map(|key_code| InputEvent::new(EventType::KEY.0, key_code.code(), 1));
+        let events = released.chain(pressed).collect::<Vec<_>>();
+
+        if !events.is_empty() {
+            log::debug!("Resyncing {} keys of the passthrough device", events.len());
+            self.emit(&events);
+        }
+    }
+
+    fn emit(&mut self, events: &[InputEvent]) {
+        if let Err(e) = self.virtual_device.emit(events) {
+            log::warn!("Failed to forward events: {e}");
+            return;
+        }
+
+        for event in events.iter() {
+            if event.event_type() != EventType::KEY {
+                continue;
+            }
+            let key_code = KeyCode::new(event.code());
+            match event.value() {
+                0 => self.held_keys.retain(|held_key| *held_key != key_code),
+                1 if !self.held_keys.contains(&key_code) => self.held_keys.push(key_code),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for Passthrough {
+    // The kernel may hand the device node to an unrelated device once this one is gone
+    fn drop(&mut self) {
+        self.own_devices.unregister(&self.dev_nodes);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    Clicker,
+    config::Config,
+    overlay::{self, Overlay},
+};
+use common::ipc::IpcEvent;
+use serde_json::{Map, Value};
+
+impl Clicker {
+    pub fn create_profile(
+        &mut self,
+        name: &str,
+        mut fields: Map<String, Value>,
+        persist: bool,
+    ) -> anyhow::Result<()> {
+        if self.raw_profile(name)?.is_some() {
+            return Err(anyhow::anyhow!("Profile `{name}` already exists"));
+        }
+
+        fields.insert("name".to_string(), Value::String(name.to_string()));
+        self.change_profile(name, Some(Value::Object(fields)), persist)
+    }
+
+    pub fn update_profile(
+        &mut self,
+        name: &str,
+        fields: Map<String, Value>,
+        persist: bool,
+    ) -> anyhow::Result<()> {
+        let Some(mut profile) = self.raw_profile(name)? else {
+            return Err(anyhow::anyhow!("Profile `{name}` doesn't exist"));
+        };
+        if fields
+            .get("name")
+            .is_some_and(|new_name| new_name.as_str() != Some(name))
+        {
+            return Err(anyhow::anyhow!(
+                "Profiles can't be renamed, add a new one and remove `{name}` instead"
+            ));
+        }
+
+        overlay::merge_fields(&mut profile, fields);
+        self.change_profile(name, Some(Value::Object(profile)), persist)
+    }
+
+    pub fn delete_profile(&mut self, name: &str, persist: bool) -> anyhow::Result<()> {
+        if self.raw_profile(name)?.is_none() {
+            return Err(anyhow::anyhow!("Profile `{name}` doesn't exist"));
+        }
+
+        self.change_profile(name, None, persist)
+    }
+
+    /// The profile as last loaded, before `extends` is resolved and defaults are filled in.
+    fn raw_profile(&self, name: &str) -> anyhow::Result<Option<Map<String, Value>>> {
+        let value = Config::merge(&self.config.raw, &self.overlay)
+            .map_err(|diagnostics| anyhow::anyhow!(diagnostics.join("\n")))?;
+
+        let profile = value
+            .get("profiles")
+            .and_then(Value::as_array)
+            .and_then(|profiles| {
+                profiles
+                    .iter()
+                    .find(|profile| profile.get("name").and_then(Value::as_str) == Some(name))
+            })
+            .and_then(Value::as_object)
+            .cloned();
+
+        Ok(profile)
+    }
+
+    /// Validates the last loaded config with the change applied, and only then applies and optionally persists it.
+    fn change_profile(
+        &mut self,
+        name: &str,
+        profile: Option<Value>,
+        persist: bool,
+    ) -> anyhow::Result<()> {
+        let mut overlay = self.overlay.clone();
+        overlay.set(name, profile.clone());
+        let config = Config::check_raw(self.config.raw.clone(), &overlay)
+            .map_err(|diagnostics| anyhow::anyhow!(diagnostics.join("\n")))?;
+
+        if persist {
+            let state = config.settings.state.as_ref().ok_or(anyhow::anyhow!(
+                "No state file configured, set `settings.state` to persist profiles"
+            ))?;
+            let mut persisted = Overlay::load(state)?;
+            persisted.set(name, profile);
+            persisted.save(state)?;
+            // The state file carries the change from now on
+            overlay.remove(name);
+        }
+
+        log::info!(
+            "Profile '{name}' changed{}",
+            if persist { " and persisted" } else { "" }
+        );
+        self.overlay = overlay;
+        self.apply_config(config);
+        let event = IpcEvent::ProfileChanged {
+            name: name.to_string(),
+        };
+        if let Err(e) = self.ipc.broadcast(&event) {
+            log::error!("Failed to broadcast event: {e}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{config, device::OwnDevices, input::KeyEvent, passthrough::Passthrough};
+use common::device::{DeviceInfo, DeviceRole};
+use evdev::{EventType, KeyCode, RelativeAxisCode, SynchronizationCode, raw_stream::RawDevice};
+use std::{
+    io,
+    os::fd::{AsFd, BorrowedFd},
+    sync::Arc,
+};
+
+/// Turns the raw events of a single device into [`KeyEvent`]s, registered as an event loop source.
+pub struct Reader {
+    device: RawDevice,
+    info: Arc<DeviceInfo>,
+    passthrough: Option<Passthrough>,
+    dropped: bool,
+}
+
+impl Reader {
+    pub fn new(
+        mut device: RawDevice,
+        info: Arc<DeviceInfo>,
+        grab: &config::Grab,
+        own_devices: &OwnDevices,
+    ) -> Self {
+        let passthrough = passthrough(&mut device, &info, grab, own_devices);
+
+        let mut reader = Self {
+            device,
+            info,
+            passthrough,
+            dropped: false,
+        };
+        reader.try_grab();
+        reader
+    }
+
+    pub fn info(&self) -> &Arc<DeviceInfo> {
+        &self.info
+    }
+
+    pub fn is_grabbed(&self) -> bool {
+        self.passthrough
+            .as_ref()
+            .is_some_and(Passthrough::is_grabbed)
+    }
+
+    /// Grabs a device meant to be grabbed once none of its keys are held anymore.
+    fn try_grab(&mut self) {
+        let Some(passthrough) = self
+            .passthrough
+            .as_mut()
+            .filter(|passthrough| !passthrough.is_grabbed())
+        else {
+            return;
+        };
+
+        match passthrough.try_grab(&mut self.device) {
+            Ok(true) => log::info!("Grabbed device {}", self.info.name),
+            Ok(false) => log::debug!(
+                "Waiting for the keys of {} to be released before grabbing it",
+                self.info.name
+            ),
+            Err(e) => {
+                log::warn!("Failed to grab device {}: {e}", self.info.name);
+                self.passthrough = None;
+            }
+        }
+    }
+
+    pub fn key_state(&self) -> Option<KeyEvent> {
+        match self.device.get_key_state() {
+            Ok(keys) => Some(KeyEvent::Resync {
+                device: Arc::clone(&self.info),
+                keys: keys.iter().collect(),
+            }),
+            Err(e) => {
+                log::warn!("Failed to query key state of {}: {e}", self.info.name);
+                None
+            }
+        }
+    }
+
+    /// Reads the pending events, fails with `WouldBlock` once the kernel buffer is drained.
+    pub fn read(&mut self) -> io::Result<Vec<KeyEvent>> {
+        let forward_motion = self.info.roles.contains(&DeviceRole::Mouse);
+        let mut key_events = Vec::new();
+        let mut resync = false;
+
+        for ev in self.device.fetch_events()? {
+            if ev.event_type() == EventType::SYNCHRONIZATION {
+                match SynchronizationCode(ev.code()) {
+                    SynchronizationCode::SYN_DROPPED => {
+                        log::warn!("Events dropped for device {}, resyncing", self.info.name);
+                        self.dropped = true;
+                    }
+                    SynchronizationCode::SYN_REPORT if self.dropped => {
+                        self.dropped = false;
+                        resync = true;
+                    }
+                    _ => {}
+                }
+                // The passthrough discards its pending frame on SYN_DROPPED, so the report
+                // ending the dropped frame flushes nothing
+                if let Some(passthrough) = self.passthrough.as_mut() {
+                    passthrough.forward(ev);
+                }
+                continue;
+            }
+
+            // Events up to the next SYN_REPORT are incomplete after a SYN_DROPPED
+            if self.dropped {
+                continue;
+            }
+
+            if let Some(passthrough) = self.passthrough.as_mut() {
+                passthrough.forward(ev);
+            }
+
+            if forward_motion && ev.event_type() == EventType::RELATIVE {
+                let axis = RelativeAxisCode(ev.code());
+                if axis == RelativeAxisCode::REL_X || axis == RelativeAxisCode::REL_Y {
+                    key_events.push(KeyEvent::Motion {
+                        device: Arc::clone(&self.info),
+                        distance: ev.value().unsigned_abs(),
+                    });
+                }
+                continue;
+            }
+
+            let EventType::KEY = ev.event_type() else {
+                continue;
+            };
+
+            let key_code = KeyCode::new(ev.code());
+            match ev.value() {
+                0 => {
+                    log::debug!("Released {key_code:?} for device {}", self.info.name);
+                    key_events.push(KeyEvent::Released {
+                        device: Arc::clone(&self.info),
+                        key_code,
+                    });
+                }
+                1 => {
+                    log::debug!("Pressed {key_code:?} for device {}", self.info.name);
+                    key_events.push(KeyEvent::Pressed {
+                        device: Arc::clone(&self.info),
+                        key_code,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if resync && let Some(key_state) = self.key_state() {
+            if let (Some(passthrough), KeyEvent::Resync { keys, .. }) =
+                (self.passthrough.as_mut(), &key_state)
+            {
+                passthrough.resync(keys);
+            }
+            key_events.push(key_state);
+        }
+
+        self.try_grab();
+
+        Ok(key_events)
+    }
+}
+
+impl AsFd for Reader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.device.as_fd()
+    }
+}
+
+/// The passthrough for a device the config grabs, the device itself is grabbed later.
+fn passthrough(
+    device: &mut RawDevice,
+    info: &DeviceInfo,
+    grab: &config::Grab,
+    own_devices: &OwnDevices,
+) -> Option<Passthrough> {
+    if !grab
+        .devices
+        .iter()
+        .any(|device_match| device_match.matches(info))
+    {
+        return None;
+    }
+
+    match Passthrough::try_new(device, &grab.keys, own_devices) {
+        Ok(passthrough) => Some(passthrough),
+        Err(e) => {
+            log::warn!("Failed to grab device {}: {e}", info.name);
+            None
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{Clicker, config::Config, device::DeviceRegistry, input};
+use calloop::{
+    Interest, LoopHandle, Mode, PostAction,
+    generic::Generic,
+    signals::{Signal, Signals},
+};
+use common::ipc::IpcEvent;
+use inotify::{Event, EventMask, Inotify, WatchDescriptor, WatchMask};
+use log::LevelFilter;
+use std::{
+    ffi::OsStr,
+    fs, io,
+    os::fd::{AsFd, BorrowedFd},
+    path::{Path, PathBuf},
+};
+
+impl Clicker {
+    /// Replaces the config with a freshly loaded one, keeping the old config when it fails to load.
+    pub fn reload_config(&mut self) -> anyhow::Result<()> {
+        let config = Config::load(&self.config_path, &self.overlay).inspect_err(|e| {
+            log::error!("Failed to reload config, keeping the current one: {e}");
+        })?;
+
+        self.apply_config(config);
+        log::info!("Config reloaded from {}", self.config_path.display());
+        if let Err(e) = self.ipc.broadcast(&IpcEvent::ConfigReloaded) {
+            log::error!("Failed to broadcast event: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Switches to an already validated config, keeping the current profile when it still exists.
+    pub fn apply_config(&mut self, config: Config) {
+        if config.virtual_device != self.config.virtual_device {
+            log::warn!("Virtual device changes take effect after a restart");
+        }
+        if config.settings.socket != self.config.settings.socket {
+            log::warn!("Socket changes take effect after a restart");
+        }
+        if config.settings.log_level != self.config.settings.log_level {
+            match self.cli_log_level {
+                Some(level) => log::warn!("Log level stays at {level}, set with --log-level"),
+                None => {
+                    let level = config.settings.log_level.unwrap_or(LevelFilter::Info);
+                    log::set_max_level(level);
+                    log::info!("Log level set to {level}");
+                }
+            }
+        }
+        self.virtual_pointer
+            .ensure_keys(&config.profiles, &self.own_devices);
+
+        let reopen_devices = !config.same_devices(&self.config);
+        self.config = config;
+
+        let previous_profile = self.current_profile.take();
+        let current_profile = previous_profile.as_ref().and_then(|previous_profile| {
+            self.config
+                .profiles
+                .iter()
+                .find(|profile| profile.name == previous_profile.name)
+                .cloned()
+        });
+        if current_profile.is_none()
+            && let Some(profile) = previous_profile.as_ref()
+        {
+            log::warn!("Profile '{}' no longer exists", profile.name);
+            self.stop_clicking();
+        }
+
+        // A profile activated by a gesture stays active unless its gestures changed
+        let keep_gestures = previous_profile
+            .as_ref()
+            .zip(current_profile.as_ref())
+            .is_some_and(|(previous, current)| input::same_gestures(previous, current));
+        self.current_profile = current_profile;
+        if !keep_gestures {
+            self.reset_gestures();
+        }
+
+        if reopen_devices {
+            log::info!("Device settings changed, reopening devices");
+            let paths = self
+                .devices
+                .iter()
+                .map(|device| device.info.path.clone())
+                .collect::<Vec<_>>();
+            for path in paths {
+                self.remove_device(&path);
+            }
+            match DeviceRegistry::event_nodes() {
+                Ok(paths) => paths.into_iter().for_each(|path| self.add_device(path)),
+                Err(e) => log::error!("Failed to list input devices: {e}"),
+            }
+        }
+    }
+}
+
+/// Symlinks followed before giving up, matching the kernel's limit.
+const MAX_SYMLINKS: usize = 40;
+
+/// Every path whose replacement changes what `config_path` resolves to, that is the path
+/// itself, each symlink on the way to the file and the paths those symlinks lead to.
+fn resolution_chain(config_path: &Path) -> Vec<PathBuf> {
+    let Ok(mut path) = std::path::absolute(config_path) else {
+        return vec![config_path.to_path_buf()];
+    };
+    let mut chain = Vec::new();
+
+    for _ in 0..MAX_SYMLINKS {
+        chain.push(path.clone());
+
+        let mut prefix = PathBuf::new();
+        let next = path.components().find_map(|component| {
+            prefix.push(component);
+            let target = fs::read_link(&prefix).ok()?;
+            let target = prefix.parent().unwrap_or(Path::new("/")).join(target);
+            let rest = path.strip_prefix(&prefix).ok()?;
+            let target = if rest.as_os_str().is_empty() {
+                target
+            } else {
+                target.join(rest)
+            };
+            Some((prefix.clone(), target))
+        });
+
+        let Some((symlink, target)) = next else {
+            break;
+        };
+        if symlink != path {
+            chain.push(symlink);
+        }
+        path = target;
+    }
+
+    chain
+}
+
+/// Inotify watches on the directories of every path in the config's [`resolution_chain`].
+struct ConfigWatch {
+    inotify: Inotify,
+    config_path: PathBuf,
+    watched: Vec<(WatchDescriptor, PathBuf)>,
+}
+
+impl ConfigWatch {
+    /// Watches the current resolution chain, after a symlink changed it points somewhere else.
+    fn update(&mut self) -> io::Result<()> {
+        for (watch_descriptor, _) in self.watched.drain(..) {
+            // Paths in the same directory share a watch, so it may already be gone
+            _ = self.inotify.watches().remove(watch_descriptor);
+        }
+
+        for path in resolution_chain(&self.config_path) {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            let watch_descriptor = self.inotify.watches().add(
+                dir,
+                WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+            )?;
+            self.watched.push((watch_descriptor, path));
+        }
+
+        Ok(())
+    }
+
+    /// Whether an event replaced or rewrote one of the watched paths.
+    fn is_relevant(&self, event: &Event<&OsStr>) -> bool {
+        let Some(path) = self
+            .watched
+            .iter()
+            .find(|(watch_descriptor, path)| {
+                *watch_descriptor == event.wd && path.file_name() == event.name
+            })
+            .map(|(_, path)| path)
+        else {
+            return false;
+        };
+
+        // A new file is still empty when it is created, but a new symlink is complete
+        !event.mask.contains(EventMask::CREATE) || path.is_symlink()
+    }
+}
+
+impl AsFd for ConfigWatch {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+/// Reloads the config whenever its file is written or replaced, and on SIGHUP.
+///
+/// Symlinks are followed, so repointing a symlink anywhere on the way to the file reloads too.
+pub fn watch(handle: &LoopHandle<'static, Clicker>, config_path: &Path) -> anyhow::Result<()> {
+    // Editors and package managers usually replace the file, so watch its directory instead
+    let mut config_watch = ConfigWatch {
+        inotify: Inotify::init()?,
+        config_path: config_path.to_path_buf(),
+        watched: Vec::new(),
+    };
+    config_watch.update()?;
+
+    let source = Generic::new(config_watch, Interest::READ, Mode::Level);
+    handle
+        .insert_source(source, move |_, config_watch, state| {
+            // SAFETY: the inotify instance is only read from, never closed or replaced
+            let config_watch = unsafe { config_watch.get_mut() };
+
+            let mut buffer = [0; 4096];
+            let relevant = match config_watch.inotify.read_events(&mut buffer) {
+                Ok(events) => events
+                    .into_iter()
+                    .any(|event| config_watch.is_relevant(&event)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(PostAction::Continue);
+                }
+                Err(e) => return Err(e),
+            };
+
+            if relevant {
+                if let Err(e) = config_watch.update() {
+                    log::warn!(
+                        "Failed to watch {}: {e}",
+                        config_watch.config_path.display()
+                    );
+                }
+                _ = state.reload_config();
+            }
+
+            Ok(PostAction::Continue)
+        })
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let signals = Signals::new(&[Signal::SIGHUP])?;
+    handle
+        .insert_source(signals, |_, _, state| {
+            log::info!("SIGHUP received, reloading config");
+            _ = state.reload_config();
+        })
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use common::KeySequence;
+use evdev::KeyCode;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct SequenceState {
+    position: usize,
+    last_press: Option<Instant>,
+}
+
+impl SequenceState {
+    /// Feeds a key press into the state machine, returns `true` when it completes the sequence.
+    pub fn advance(&mut self, sequence: &KeySequence, key_code: KeyCode, now: Instant) -> bool {
+        if sequence.keys.is_empty() {
+            return false;
+        }
+
+        let timed_out = self.last_press.is_some_and(|last_press| {
+            now.duration_since(last_press) > Duration::from_millis(sequence.timeout)
+        });
+        if timed_out {
+            self.reset();
+        }
+
+        if sequence.keys.get(self.position) == Some(&key_code) {
+            self.position += 1;
+            self.last_press = Some(now);
+        } else if sequence.keys.first() == Some(&key_code) {
+            self.position = 1;
+            self.last_press = Some(now);
+        } else {
+            self.reset();
+        }
+
+        if self.position == sequence.keys.len() {
+            self.reset();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.last_press = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(keys: &[KeyCode]) -> KeySequence {
+        KeySequence {
+            keys: keys.to_vec(),
+            timeout: 500,
+        }
+    }
+
+    fn ms(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn completes_in_order() {
+        let sequence = sequence(&[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_A, KeyCode::KEY_B]);
+        let mut state = SequenceState::default();
+        let start = Instant::now();
+
+        assert!(!state.advance(&sequence, KeyCode::KEY_LEFTCTRL, start));
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, ms(start, 100)));
+        assert!(state.advance(&sequence, KeyCode::KEY_B, ms(start, 200)));
+        // Completing resets, so the next press starts over
+        assert!(!state.advance(&sequence, KeyCode::KEY_B, ms(start, 300)));
+    }
+
+    #[test]
+    fn times_out_between_presses() {
+        let sequence = sequence(&[KeyCode::KEY_A, KeyCode::KEY_B]);
+        let mut state = SequenceState::default();
+        let start = Instant::now();
+
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, start));
+        assert!(!state.advance(&sequence, KeyCode::KEY_B, ms(start, 501)));
+
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, ms(start, 1000)));
+        assert!(state.advance(&sequence, KeyCode::KEY_B, ms(start, 1500)));
+    }
+
+    #[test]
+    fn wrong_key_mid_sequence_resets() {
+        let sequence = sequence(&[KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C]);
+        let mut state = SequenceState::default();
+        let start = Instant::now();
+
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, start));
+        assert!(!state.advance(&sequence, KeyCode::KEY_X, ms(start, 10)));
+        assert!(!state.advance(&sequence, KeyCode::KEY_B, ms(start, 20)));
+        assert!(!state.advance(&sequence, KeyCode::KEY_C, ms(start, 30)));
+    }
+
+    #[test]
+    fn first_key_mid_sequence_restarts() {
+        let sequence = sequence(&[KeyCode::KEY_A, KeyCode::KEY_B]);
+        let mut state = SequenceState::default();
+        let start = Instant::now();
+
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, start));
+        assert!(!state.advance(&sequence, KeyCode::KEY_A, ms(start, 10)));
+        assert!(state.advance(&sequence, KeyCode::KEY_B, ms(start, 20)));
+    }
+
+    #[test]
+    fn empty_sequence_never_completes() {
+        let mut state = SequenceState::default();
+        assert!(!state.advance(&sequence(&[]), KeyCode::KEY_A, Instant::now()));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{config::Config, inherit, input};
+use common::Profile;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Longest virtual device name uinput takes, its 80 byte buffer also holds the terminating NUL.
+const MAX_DEVICE_NAME_LEN: usize = 78;
+
+/// Deserializes an evaluated config, reporting unknown keys and invalid values by location.
+pub fn deserialize(mut value: Value) -> Result<Config, Vec<String>> {
+    let diagnostics = inherit::resolve(&mut value);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    let value = &value;
+
+    let mut unknown_keys = Vec::new();
+    let mut track = serde_path_to_error::Track::new();
+    let deserializer = serde_path_to_error::Deserializer::new(value, &mut track);
+
+    let config: Config = match serde_ignored::deserialize(deserializer, |path| {
+        let mut segments = Vec::new();
+        ignored_segments(&path, &mut segments);
+        unknown_keys.push(segments);
+    }) {
+        Ok(config) => config,
+        Err(e) => {
+            let segments = track
+                .path()
+                .iter()
+                .filter_map(|segment| match segment {
+                    serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+                    serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            return Err(vec![format!("{}: {e}", describe(&segments, value))]);
+        }
+    };
+
+    let mut diagnostics = unknown_keys
+        .iter()
+        .map(|segments| format!("{}: unknown key", describe(segments, value)))
+        .collect::<Vec<_>>();
+    diagnostics.extend(validate(&config));
+
+    if diagnostics.is_empty() {
+        Ok(config)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Checks the values that deserialize fine but can't work at runtime.
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut diagnostics = Vec::new();
+
+    let name = &config.virtual_device.name;
+    if name.len() > MAX_DEVICE_NAME_LEN {
+        diagnostics.push(format!(
+            "virtual_device.name: must be at most {MAX_DEVICE_NAME_LEN} bytes, got {}",
+            name.len()
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for profile in config.profiles.iter() {
+        if !names.insert(profile.name.as_str()) {
+            diagnostics.push(format!(
+                "profile '{}': name is used by another profile",
+                profile.name
+            ));
+        }
+        validate_profile(profile, &mut diagnostics);
+    }
+
+    diagnostics
+}
+
+fn validate_profile(profile: &Profile, diagnostics: &mut Vec<String>) {
+    let mut report = |field: &str, message: String| {
+        diagnostics.push(format!("profile '{}': {field}: {message}", profile.name));
+    };
+
+    if !profile.cps.target.is_finite() || profile.cps.target <= 0. {
+        report(
+            "cps.target",
+            format!("must be positive, got {}", profile.cps.target),
+        );
+    } else if profile.cps.std_dev == 0. && profile.cps.target <= 0.5 {
+        report(
+            "cps.target",
+            format!(
+                "must be above 0.5 when cps.std_dev is 0, got {}",
+                profile.cps.target
+            ),
+        );
+    }
+    if !profile.cps.std_dev.is_finite() || profile.cps.std_dev < 0. {
+        report(
+            "cps.std_dev",
+            format!("must not be negative, got {}", profile.cps.std_dev),
+        );
+    }
+    if !profile.jitter.is_finite() || profile.jitter < 0. {
+        report(
+            "jitter",
+            format!("must not be negative, got {}", profile.jitter),
+        );
+    }
+
+    if profile.activation_keys.is_none() && !input::has_gestures(profile) {
+        report(
+            "activation_keys",
+            "must be set without an activation_sequence, double_tap or long_press".to_string(),
+        );
+    }
+
+    let activation_keys = profile.activation_keys.as_deref().unwrap_or_default();
+    let trigger = profile.trigger();
+    if activation_keys.contains(&trigger) {
+        report(
+            "activation_keys",
+            format!("contains the trigger key {trigger:?}, so the chord can never be completed"),
+        );
+    }
+    let mut chord = HashSet::new();
+    for key_code in activation_keys.iter() {
+        if !chord.insert(key_code) {
+            report("activation_keys", format!("{key_code:?} is listed twice"));
+        }
+    }
+
+    if let Some(sequence) = profile.activation_sequence.as_ref()
+        && sequence.keys.is_empty()
+    {
+        report("activation_sequence.keys", "must not be empty".to_string());
+    }
+}
+
+fn ignored_segments(path: &serde_ignored::Path, segments: &mut Vec<String>) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            ignored_segments(parent, segments);
+            segments.push(index.to_string());
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            ignored_segments(parent, segments);
+            segments.push(key.clone());
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => ignored_segments(parent, segments),
+    }
+}
+
+/// Formats a location in the config, naming profiles instead of numbering them.
+fn describe(segments: &[String], value: &Value) -> String {
+    if let [profiles, index, rest @ ..] = segments
+        && profiles == "profiles"
+        && let Ok(index) = index.parse::<usize>()
+    {
+        let name = value["profiles"][index]["name"]
+            .as_str()
+            .map(|name| format!("profile '{name}'"))
+            .unwrap_or_else(|| format!("profile #{index}"));
+        return if rest.is_empty() {
+            name
+        } else {
+            format!("{name}: {}", rest.join("."))
+        };
+    }
+
+    if segments.is_empty() {
+        "config".to_string()
+    } else {
+        segments.join(".")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A valid profile named `name` with `fields` merged over it.
+    fn profile(name: &str, fields: Value) -> Value {
+        let mut profile = json!({
+            "name": name,
+            "activation_keys": ["KEY_F8"],
+            "cps": { "target": 10 },
+        });
+        crate::overlay::merge_fields(
+            profile.as_object_mut().unwrap(),
+            fields.as_object().unwrap().clone(),
+        );
+        profile
+    }
+
+    fn diagnostics(profiles: &[Value]) -> Vec<String> {
+        deserialize(json!({ "profiles": profiles }))
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn valid_config_has_no_diagnostics() {
+        assert!(diagnostics(&[profile("a", json!({}))]).is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        assert_eq!(
+            diagnostics(&[profile("a", json!({ "cps": { "targte": 5 } }))]),
+            vec!["profile 'a': cps.targte: unknown key"]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values_by_location() {
+        let diagnostics = diagnostics(&[profile("a", json!({ "jitter": "lots" }))]);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].starts_with("profile 'a': jitter: "));
+    }
+
+    #[test]
+    fn reports_inheritance_errors() {
+        assert_eq!(
+            diagnostics(&[profile("a", json!({ "extends": "missing" }))]),
+            vec!["profile 'a': extends: profile 'missing' doesn't exist"]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_names() {
+        let profiles = [
+            profile("a", json!({})),
+            profile("a", json!({ "activation_keys": ["KEY_F9"] })),
+        ];
+        assert_eq!(
+            diagnostics(&profiles),
+            vec!["profile 'a': name is used by another profile"]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_cps_and_jitter() {
+        let profiles = [
+            profile("zero", json!({ "cps": { "target": 0 } })),
+            profile(
+                "negative",
+                json!({ "activation_keys": ["KEY_F9"], "cps": { "std_dev": -1 }, "jitter": -2 }),
+            ),
+        ];
+        assert_eq!(
+            diagnostics(&profiles),
+            vec![
+                "profile 'zero': cps.target: must be positive, got 0",
+                "profile 'negative': cps.std_dev: must not be negative, got -1",
+                "profile 'negative': jitter: must not be negative, got -2",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_low_target_without_deviation() {
+        assert_eq!(
+            diagnostics(&[profile(
+                "a",
+                json!({ "cps": { "target": 0.5, "std_dev": 0 } })
+            )]),
+            vec!["profile 'a': cps.target: must be above 0.5 when cps.std_dev is 0, got 0.5"]
+        );
+        assert!(diagnostics(&[profile("a", json!({ "cps": { "target": 0.5 } }))]).is_empty());
+    }
+
+    #[test]
+    fn reports_profiles_without_activation() {
+        assert_eq!(
+            diagnostics(&[profile("a", json!({ "activation_keys": null }))]),
+            vec![
+                "profile 'a': activation_keys: must be set without an activation_sequence, double_tap or long_press"
+            ]
+        );
+        let double_tap = json!({ "activation_keys": null, "double_tap": { "key": "KEY_F8" } });
+        assert!(diagnostics(&[profile("a", double_tap)]).is_empty());
+        // An empty list keeps the profile always active
+        assert!(diagnostics(&[profile("a", json!({ "activation_keys": [] }))]).is_empty());
+    }
+
+    #[test]
+    fn reports_broken_chords() {
+        let profiles = [
+            profile(
+                "trigger",
+                json!({ "activation_keys": ["KEY_F8", "BTN_LEFT"] }),
+            ),
+            profile("twice", json!({ "activation_keys": ["KEY_F9", "KEY_F9"] })),
+        ];
+        assert_eq!(
+            diagnostics(&profiles),
+            vec![
+                "profile 'trigger': activation_keys: contains the trigger key BTN_LEFT, so the chord can never be completed",
+                "profile 'twice': activation_keys: KEY_F9 is listed twice",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_long_virtual_device_names() {
+        let config = |name: String| {
+            deserialize(json!({
+                "profiles": [profile("a", json!({}))],
+                "virtual_device": { "name": name },
+            }))
+            .err()
+            .unwrap_or_default()
+        };
+        assert!(config("x".repeat(78)).is_empty());
+        assert_eq!(
+            config("x".repeat(79)),
+            vec!["virtual_device.name: must be at most 78 bytes, got 79"]
+        );
+    }
+
+    #[test]
+    fn profiles_may_share_chords() {
+        let profiles = [
+            profile("default", json!({})),
+            json!({ "name": "fast", "extends": "default", "cps": { "target": 25 } }),
+        ];
+        assert!(diagnostics(&profiles).is_empty());
+    }
+
+    #[test]
+    fn reports_empty_sequences() {
+        let sequence = json!({ "activation_keys": [], "activation_sequence": { "keys": [] } });
+        assert_eq!(
+            diagnostics(&[profile("a", sequence)]),
+            vec!["profile 'a': activation_sequence.keys: must not be empty"]
+        );
+    }
+}
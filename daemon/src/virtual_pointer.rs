@@ -1,15 +1,53 @@
-use crate::{Clicker, config};
+use crate::{
+    Clicker,
+    config::{self, VirtualDeviceConfig},
+    device::OwnDevices,
+};
 use calloop::timer::{TimeoutAction, Timer};
+use common::{Profile, device::VirtualDeviceInfo};
 use evdev::uinput::VirtualDevice;
-use evdev::{AttributeSet, EventType, InputEvent, KeyCode, RelativeAxisCode};
+use evdev::{AttributeSet, EventType, InputEvent, InputId, KeyCode, RelativeAxisCode};
 use rand::prelude::*;
 use rand_distr::{Distribution, Normal, Poisson};
-use std::time::{Duration, Instant};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 pub static POISSON_LAMBDA_FACTOR: f64 = 1.0;
+/// Draws of a window's CPS before settling for a single click, a target near zero rarely draws above 0.5.
+const MAX_CPS_SAMPLES: usize = 100;
+
+fn build_device(
+    identity: &VirtualDeviceConfig,
+    keys: &AttributeSet<KeyCode>,
+    own_devices: &OwnDevices,
+) -> anyhow::Result<(VirtualDevice, Vec<PathBuf>)> {
+    let mut relative_axes = AttributeSet::new();
+    relative_axes.insert(RelativeAxisCode::REL_X);
+    relative_axes.insert(RelativeAxisCode::REL_Y);
+
+    let mut virtual_device = VirtualDevice::builder()?
+        .name(&identity.name)
+        .input_id(InputId::new(
+            identity.bustype,
+            identity.vendor,
+            identity.product,
+            identity.version,
+        ))
+        .with_keys(keys)?
+        .with_relative_axes(&relative_axes)?
+        .build()?;
+    let dev_nodes = own_devices.register(&mut virtual_device)?;
+
+    Ok((virtual_device, dev_nodes))
+}
 
 pub struct VirtualPointer {
     virtual_device: VirtualDevice,
+    identity: VirtualDeviceConfig,
+    keys: AttributeSet<KeyCode>,
+    dev_nodes: Vec<PathBuf>,
     last_window_start: Option<Instant>,
     clicks_in_current_window: u32,
     current_window_target: u32,
@@ -17,24 +55,27 @@ pub struct VirtualPointer {
 }
 
 impl VirtualPointer {
-    pub fn try_new(config: &config::Config) -> anyhow::Result<Self> {
+    pub fn try_new(config: &config::Config, own_devices: &OwnDevices) -> anyhow::Result<Self> {
         let mut keys = AttributeSet::new();
         for profile in config.profiles.iter() {
             keys.insert(profile.repeat_key);
         }
 
-        let mut relative_axes = AttributeSet::new();
-        relative_axes.insert(RelativeAxisCode::REL_X);
-        relative_axes.insert(RelativeAxisCode::REL_Y);
-
-        let virtual_device = VirtualDevice::builder()?
-            .name("clicker-rs")
-            .with_keys(&keys)?
-            .with_relative_axes(&relative_axes)?
-            .build()?;
+        let identity = config.virtual_device.clone();
+        let (virtual_device, dev_nodes) = build_device(&identity, &keys, own_devices)?;
+        log::info!(
+            "Virtual device '{}' ({:04x}:{:04x}) created at {:?}",
+            identity.name,
+            identity.vendor,
+            identity.product,
+            dev_nodes
+        );
 
         Ok(Self {
             virtual_device,
+            identity,
+            keys,
+            dev_nodes,
             last_window_start: None,
             clicks_in_current_window: 0,
             current_window_target: 0,
@@ -42,6 +83,45 @@ impl VirtualPointer {
         })
     }
 
+    /// Rebuilds the device when a profile repeats a key it can't emit yet.
+    ///
+    /// Every click is a complete press and release, so swapping the device between two clicks
+    /// doesn't leave a key stuck and the click timer simply continues on the new device.
+    pub fn ensure_keys(&mut self, profiles: &[Profile], own_devices: &OwnDevices) {
+        let missing = profiles
+            .iter()
+            .map(|profile| profile.repeat_key)
+            .filter(|key| !self.keys.contains(*key))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut keys = self.keys.clone();
+        missing.iter().for_each(|key| keys.insert(*key));
+
+        match build_device(&self.identity, &keys, own_devices) {
+            Ok((virtual_device, dev_nodes)) => {
+                log::info!("Rebuilt virtual device to emit {missing:?}");
+                self.virtual_device = virtual_device;
+                self.keys = keys;
+                own_devices.unregister(&std::mem::replace(&mut self.dev_nodes, dev_nodes));
+            }
+            Err(e) => log::warn!("Failed to rebuild virtual device for {missing:?}: {e}"),
+        }
+    }
+
+    pub fn info(&self) -> VirtualDeviceInfo {
+        VirtualDeviceInfo {
+            name: self.identity.name.clone(),
+            vendor: self.identity.vendor,
+            product: self.identity.product,
+            version: self.identity.version,
+            bustype: format!("{:?}", self.identity.bustype),
+            dev_nodes: self.dev_nodes.clone(),
+        }
+    }
+
     pub fn click(&mut self, button: KeyCode) {
         self.virtual_device
             .emit(&[
@@ -67,14 +147,19 @@ impl VirtualPointer {
                 if let Some(profile) = state.current_profile.as_ref() {
                     let mut rng = rand::rng();
                     let gaussian =
-                        Normal::new(profile.cps.target as f64, profile.cps.std_dev as f64).unwrap();
-
-                    let window_average_cps = loop {
-                        let sample = gaussian.sample(&mut rng);
-                        if sample > 0.5 {
-                            break sample.round() as f32;
-                        }
-                    };
+                        match Normal::new(profile.cps.target as f64, profile.cps.std_dev as f64) {
+                            Ok(gaussian) => gaussian,
+                            Err(e) => {
+                                log::error!("Invalid CPS in profile '{}': {e}", profile.name);
+                                state.registration_token = None;
+                                return TimeoutAction::Drop;
+                            }
+                        };
+
+                    let window_average_cps = (0..MAX_CPS_SAMPLES)
+                        .map(|_| gaussian.sample(&mut rng))
+                        .find(|sample| *sample > 0.5)
+                        .map_or(1., |sample| sample.round() as f32);
 
                     let poisson =
                         Poisson::new(window_average_cps as f64 * POISSON_LAMBDA_FACTOR).unwrap();
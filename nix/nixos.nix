@@ -46,6 +46,8 @@ in
         Group = cfg.group;
         RuntimeDirectory = "clicker-rs";
         RuntimeDirectoryMode = "0750";
+        # /var/lib/clicker-rs, for `settings.state`
+        StateDirectory = "clicker-rs";
         ExecStart = "${lib.getExe cfg.package}";
         Restart = "on-failure";
 